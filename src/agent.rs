@@ -0,0 +1,205 @@
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::board::Board;
+use crate::book::{Book, BookPlayer};
+use crate::eval::EvalWeights;
+use crate::level::{self, Level, Style};
+use crate::logic::Move;
+use crate::mcts::{Mcts, MctsOptions, Playout};
+use crate::protocol::ExternalEngine;
+use crate::rng::Rng;
+use crate::search::{Engine, EngineOptions, Limits, MAX_THREADS};
+use crate::tablebase::Tablebase;
+
+pub const DEFAULT_MOVETIME: Duration = Duration::from_millis(1000);
+
+// Anything that can pick a move for the side to move: the search engine,
+// the tree search, an external program. The GUI runs agents on a worker
+// thread, so they must be Send, and stop is raised when the answer is no
+// longer wanted. People are not agents: their moves arrive as events on
+// the GUI thread, through selection, dragging, confirmation and puzzle
+// checking, and a blocking choose_move would only wrap that in a channel.
+// Both end up in App::play_move, which is where the two kinds of player
+// meet. Ok(None) means there was no move to make, an error that the
+// player itself broke down.
+pub trait Agent: Send {
+    fn name(&self) -> String;
+
+    fn choose_move(&mut self, board: &Board, stop: &AtomicBool) -> Result<Option<Move>, String>;
+
+    fn new_game(&mut self) {}
+
+    // whether the last move came from an opening book rather than thought
+    fn played_from_book(&self) -> bool {
+        false
+    }
+}
+
+pub struct AlphaBeta {
+    pub engine: Engine,
+    pub limits: Limits,
+    level: Level,
+    rng: Rng
+}
+
+impl AlphaBeta {
+    pub fn new(weights: EvalWeights, limits: Limits) -> AlphaBeta {
+        AlphaBeta::with_engine(Engine::new(weights), limits)
+    }
+
+    pub fn with_engine(engine: Engine, limits: Limits) -> AlphaBeta {
+        AlphaBeta {
+            engine,
+            limits,
+            level: level::FULL_STRENGTH,
+            rng: Rng::from_time()
+        }
+    }
+
+    pub fn set_level(&mut self, level: Level) {
+        self.engine.set_multi_pv(level.lines);
+        self.level = level;
+    }
+
+    // the style's weights replace the engine's
+    pub fn set_style(&mut self, style: Style, weights: &EvalWeights) {
+        self.engine.weights = style.apply(weights);
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+}
+
+impl Agent for AlphaBeta {
+    fn name(&self) -> String {
+        if self.level == level::FULL_STRENGTH {
+            "alpha-beta".to_string()
+        } else {
+            format!("alpha-beta, {}", self.level.name)
+        }
+    }
+
+    fn choose_move(&mut self, board: &Board, stop: &AtomicBool) -> Result<Option<Move>, String> {
+        let info = self.engine.search(board, self.level.limit(self.limits), stop, |_| {});
+        Ok(info.and_then(|info| self.level.choose(&info.lines, &mut self.rng)))
+    }
+
+    fn new_game(&mut self) {
+        self.engine.new_game();
+    }
+}
+
+// Builds a player from a description such as
+//
+//   alphabeta depth=4
+//   alphabeta movetime=200 hash=32 threads=4
+//   mcts iterations=2000 playout=random seed=7
+//   engine movetime=200 -- ./hexchess-engine --some-flag
+//   alphabeta depth=4 book=book.bin
+//   alphabeta movetime=500 tablebases=tablebases
+//   alphabeta movetime=500 level=casual style=aggressive seed=3
+//
+// Everything after "--" is the command line of an external engine. Any
+// player can be given an opening book to play from before it thinks.
+pub fn from_spec(spec: &str, weights: &EvalWeights) -> Result<Box<dyn Agent>, String> {
+    let (spec, command) = match spec.find(" -- ") {
+        Some(index) => (&spec[..index], Some(spec[index + 4..].trim())),
+        None => (spec, None)
+    };
+    let mut words = spec.split_whitespace();
+    let kind = words.next().ok_or("empty player description")?;
+    let mut settings = vec![];
+    let mut book = None;
+    for word in words {
+        let (key, value) = word.split_once('=')
+            .ok_or_else(|| format!("expected key=value, found {}", word))?;
+        if key == "book" {
+            book = Some(Arc::new(Book::load(value)?));
+        } else {
+            settings.push((key, value));
+        }
+    }
+    let player = player_from_settings(kind, settings, command, weights)?;
+    Ok(match book {
+        Some(book) => Box::new(BookPlayer::new(book, player)),
+        None => player
+    })
+}
+
+fn player_from_settings(kind: &str, settings: Vec<(&str, &str)>, command: Option<&str>, weights: &EvalWeights) -> Result<Box<dyn Agent>, String> {
+    let number = |value: &str| value.parse::<u64>().map_err(|e| format!("{}: {}", value, e));
+    match kind {
+        "alphabeta" => {
+            let mut limits = Limits::default();
+            let mut options = EngineOptions::default();
+            let mut tablebase = None;
+            let mut level = level::FULL_STRENGTH;
+            let mut style = Style::Balanced;
+            let mut seed = None;
+            for (key, value) in settings {
+                match key {
+                    "depth" => limits.depth = Some(number(value)? as u32),
+                    "movetime" => limits.movetime = Some(Duration::from_millis(number(value)?)),
+                    "hash" => options.hash_mb = number(value)? as usize,
+                    "threads" => options.threads = (number(value)? as usize).clamp(1, MAX_THREADS),
+                    "tablebases" => tablebase = Some(Arc::new(Tablebase::load_dir(Path::new(value))?)),
+                    "level" => level = Level::parse(value)?,
+                    "style" => style = Style::parse(value)?,
+                    "seed" => seed = Some(number(value)?),
+                    _ => return Err(format!("alphabeta has no setting {}", key))
+                }
+            }
+            if limits.depth.is_none() && limits.movetime.is_none() && level.depth.is_none() {
+                limits.movetime = Some(DEFAULT_MOVETIME);
+            }
+            let mut engine = Engine::with_options(weights.clone(), options);
+            engine.set_tablebase(tablebase);
+            let mut player = AlphaBeta::with_engine(engine, limits);
+            player.set_level(level);
+            player.set_style(style, weights);
+            if let Some(seed) = seed {
+                player.set_seed(seed);
+            }
+            Ok(Box::new(player))
+        },
+        "mcts" => {
+            let mut options = MctsOptions {
+                movetime: None,
+                ..Default::default()
+            };
+            for (key, value) in settings {
+                match key {
+                    "iterations" => options.iterations = Some(number(value)? as u32),
+                    "movetime" => options.movetime = Some(Duration::from_millis(number(value)?)),
+                    "seed" => options.seed = number(value)?,
+                    "plies" => options.max_playout_plies = number(value)? as u32,
+                    "playout" => options.playout = match value {
+                        "random" => Playout::Random,
+                        "heuristic" => Playout::Heuristic,
+                        _ => return Err(format!("unknown playout: {}", value))
+                    },
+                    _ => return Err(format!("mcts has no setting {}", key))
+                }
+            }
+            if options.iterations.is_none() && options.movetime.is_none() {
+                options.movetime = Some(DEFAULT_MOVETIME);
+            }
+            Ok(Box::new(Mcts::new(options)))
+        },
+        "engine" => {
+            let command = command.ok_or("engine needs a command after --")?;
+            let mut movetime = DEFAULT_MOVETIME;
+            for (key, value) in settings {
+                match key {
+                    "movetime" => movetime = Duration::from_millis(number(value)?),
+                    _ => return Err(format!("engine has no setting {}", key))
+                }
+            }
+            Ok(Box::new(ExternalEngine::launch(command, movetime)?))
+        },
+        _ => Err(format!("unknown player: {}", kind))
+    }
+}
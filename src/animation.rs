@@ -0,0 +1,168 @@
+use crate::board::{is_queening_hex, Board, Player};
+use crate::logic::Move;
+use crate::pieces::Piece;
+
+// A move being shown on the board after it has been made. The board is
+// already in the new position, so nothing waits on an animation; the GUI
+// just draws the moving piece between its hexes until it has finished.
+// Knights hop over the pieces in their way and take an arc, a taken piece
+// fades out as the mover arrives and a pawn reaching the far side turns
+// into a queen on the way.
+
+// seconds from start to finish
+pub const MOVE_DURATION: f32 = 0.3;
+// the top of a knight's arc, in tiles
+pub const HOP_HEIGHT: f32 = 0.8;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Animation {
+    pub player: Player,
+    pub piece: Piece,
+    // what the piece becomes when it arrives, if it changes
+    pub promotion: Option<Piece>,
+    pub captured: Option<(Player, Piece)>,
+    pub from: usize,
+    pub to: usize,
+    // board positions
+    start: [f32; 2],
+    end: [f32; 2],
+    elapsed: f32
+}
+
+impl Animation {
+    // from the board before the move
+    pub fn new(board: &Board, m: Move) -> Option<Animation> {
+        let (player, piece) = board.hexes[m.from]?;
+        let promotion = if piece == Piece::Pawn && is_queening_hex(m.to, player) { Some(Piece::Queen) } else { None };
+        Some(Animation {
+            player,
+            piece,
+            promotion,
+            captured: board.hexes[m.to],
+            from: m.from,
+            to: m.to,
+            start: board.positions[m.from],
+            end: board.positions[m.to],
+            elapsed: 0.0
+        })
+    }
+
+    // the piece was carried to its hex by hand, only what happens when it
+    // arrives is left to show
+    pub fn dropped(&mut self) {
+        self.start = self.end;
+    }
+
+    // false once it has finished
+    pub fn advance(&mut self, seconds: f32) -> bool {
+        self.elapsed += seconds;
+        self.elapsed < MOVE_DURATION
+    }
+
+    // how far along it is, easing in and out
+    pub fn progress(&self) -> f32 {
+        let t = (self.elapsed / MOVE_DURATION).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    // where the moving piece is, in board positions
+    pub fn position(&self) -> [f32; 2] {
+        let t = self.progress();
+        [
+            self.start[0] + (self.end[0] - self.start[0]) * t,
+            self.start[1] + (self.end[1] - self.start[1]) * t
+        ]
+    }
+
+    // how far above its path the piece is, in tiles, whichever way up the
+    // board is drawn
+    pub fn lift(&self) -> f32 {
+        if self.piece != Piece::Knight || self.start == self.end {
+            return 0.0;
+        }
+        let t = self.progress();
+        4.0 * HOP_HEIGHT * t * (1.0 - t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Hexes, HEX_COUNT};
+    use crate::logic;
+
+    fn position(pieces: &[(usize, Player, Piece)]) -> Board {
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        for &(hex, player, piece) in pieces {
+            hexes[hex] = Some((player, piece));
+        }
+        Board::from_hexes(hexes, Player::White)
+    }
+
+    // the first legal move of a piece of that kind
+    fn moving(board: &Board, kind: Piece) -> Animation {
+        let m = logic::legal_moves(board).into_iter()
+            .find(|m| matches!(board.hexes[m.from], Some((_, piece)) if piece == kind))
+            .unwrap();
+        Animation::new(board, m).unwrap()
+    }
+
+    #[test]
+    fn progress_is_clamped_and_eased() {
+        let mut animation = moving(&Board::new(), Piece::Pawn);
+        assert_eq!(animation.progress(), 0.0);
+        animation.elapsed = MOVE_DURATION / 4.0;
+        // slower than a straight line at the start
+        assert!(animation.progress() < 0.25);
+        animation.elapsed = MOVE_DURATION / 2.0;
+        assert!((animation.progress() - 0.5).abs() < 1e-6);
+        animation.elapsed = MOVE_DURATION;
+        assert_eq!(animation.progress(), 1.0);
+        animation.elapsed = MOVE_DURATION * 2.0;
+        assert_eq!(animation.progress(), 1.0);
+        assert_eq!(animation.position(), animation.end);
+        animation.elapsed = -1.0;
+        assert_eq!(animation.position(), animation.start);
+    }
+
+    #[test]
+    fn only_knights_hop() {
+        let board = Board::new();
+        let mut knight = moving(&board, Piece::Knight);
+        knight.elapsed = MOVE_DURATION / 2.0;
+        assert!((knight.lift() - HOP_HEIGHT).abs() < 1e-6);
+        let mut pawn = moving(&board, Piece::Pawn);
+        pawn.elapsed = MOVE_DURATION / 2.0;
+        assert_eq!(pawn.lift(), 0.0);
+        // a knight put down by hand has already landed
+        knight.dropped();
+        assert_eq!(knight.lift(), 0.0);
+        assert_eq!(knight.position(), knight.end);
+    }
+
+    #[test]
+    fn advancing_finishes_after_the_duration() {
+        let mut animation = moving(&Board::new(), Piece::Pawn);
+        assert!(animation.advance(MOVE_DURATION / 2.0));
+        assert!(!animation.advance(MOVE_DURATION));
+        assert_eq!(animation.progress(), 1.0);
+    }
+
+    #[test]
+    fn promotions_and_captures_are_noticed() {
+        let board = position(&[
+            (8, Player::White, Piece::Pawn),
+            (45, Player::Black, Piece::Pawn),
+            (46, Player::White, Piece::Queen)
+        ]);
+        let queening = logic::legal_moves(&board).into_iter()
+            .find(|m| m.from == 8 && is_queening_hex(m.to, Player::White))
+            .unwrap();
+        let promotion = Animation::new(&board, queening).unwrap();
+        assert_eq!((promotion.piece, promotion.promotion, promotion.captured), (Piece::Pawn, Some(Piece::Queen), None));
+        let capture = Animation::new(&board, Move { from: 46, to: 45 }).unwrap();
+        assert_eq!(capture.promotion, None);
+        assert_eq!(capture.captured, Some((Player::Black, Piece::Pawn)));
+        assert!(Animation::new(&board, Move { from: 0, to: 1 }).is_none());
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use crate::notation;
+use crate::record::GameRecord;
+
+// Circles and arrows drawn on the board to talk about a position. In game
+// records they go in the comment after the move that reached the position:
+//
+//   {[%draw green e4, red c1-d3] any other comment}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mark {
+    Circle(usize),
+    Arrow(usize, usize)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Colour {
+    Green,
+    Red,
+    Blue,
+    Yellow
+}
+
+pub const COLOURS: [Colour; 4] = [Colour::Green, Colour::Red, Colour::Blue, Colour::Yellow];
+
+impl Colour {
+    pub fn name(self) -> &'static str {
+        match self {
+            Colour::Green => "green",
+            Colour::Red => "red",
+            Colour::Blue => "blue",
+            Colour::Yellow => "yellow"
+        }
+    }
+
+    pub fn rgb(self) -> [f32; 3] {
+        match self {
+            Colour::Green => [0.1, 0.75, 0.2],
+            Colour::Red => [0.9, 0.15, 0.1],
+            Colour::Blue => [0.15, 0.4, 0.95],
+            Colour::Yellow => [0.95, 0.8, 0.1]
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Annotation {
+    pub mark: Mark,
+    pub colour: Colour
+}
+
+// drawing the same mark again takes it away, in another colour recolours it
+pub fn toggle(annotations: &mut Vec<Annotation>, annotation: Annotation) {
+    match annotations.iter().position(|a| a.mark == annotation.mark) {
+        Some(index) if annotations[index].colour == annotation.colour => {
+            annotations.remove(index);
+        },
+        Some(index) => annotations[index].colour = annotation.colour,
+        None => annotations.push(annotation)
+    }
+}
+
+fn mark_text(mark: Mark) -> String {
+    match mark {
+        Mark::Circle(hex) => notation::hex_name(hex),
+        Mark::Arrow(from, to) => format!("{}-{}", notation::hex_name(from), notation::hex_name(to))
+    }
+}
+
+pub fn to_comment(annotations: &[Annotation]) -> String {
+    let marks: Vec<String> = annotations.iter()
+        .map(|a| format!("{} {}", a.colour.name(), mark_text(a.mark)))
+        .collect();
+    format!("[%draw {}]", marks.join(", "))
+}
+
+fn parse_annotation(text: &str) -> Option<Annotation> {
+    let (colour, mark) = text.trim().split_once(' ')?;
+    let colour = *COLOURS.iter().find(|c| c.name() == colour)?;
+    let mark = match mark.split_once('-') {
+        Some((from, to)) => Mark::Arrow(notation::parse_hex(from)?, notation::parse_hex(to)?),
+        None => Mark::Circle(notation::parse_hex(mark)?)
+    };
+    Some(Annotation { mark, colour })
+}
+
+// the annotations in a comment and whatever else it says
+pub fn parse_comment(comment: &str) -> Result<(Vec<Annotation>, String), String> {
+    let (before, draw) = match comment.split_once("[%draw") {
+        Some(split) => split,
+        None => return Ok((vec![], comment.to_string()))
+    };
+    let (marks, after) = draw.split_once(']').ok_or("unclosed [%draw")?;
+    let annotations = marks.split(',')
+        .filter(|text| !text.trim().is_empty())
+        .map(|text| parse_annotation(text).ok_or_else(|| format!("bad annotation: {}", text.trim())))
+        .collect::<Result<_, _>>()?;
+    let rest = format!("{} {}", before.trim(), after.trim());
+    Ok((annotations, rest.trim().to_string()))
+}
+
+// the drawings in a record's comments, keyed like the GUI keeps them by
+// how many moves had been played
+pub fn from_record(record: &GameRecord) -> Result<HashMap<usize, Vec<Annotation>>, String> {
+    let mut annotations: HashMap<usize, Vec<Annotation>> = HashMap::new();
+    for (ply, comment) in &record.comments {
+        let (marks, _) = parse_comment(comment)?;
+        if !marks.is_empty() {
+            annotations.entry(*ply).or_default().extend(marks);
+        }
+    }
+    Ok(annotations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mark(mark: Mark, colour: Colour) -> Annotation {
+        Annotation { mark, colour }
+    }
+
+    #[test]
+    fn comments_round_trip() {
+        let annotations = vec![
+            mark(Mark::Circle(45), Colour::Green),
+            mark(Mark::Arrow(0, 90), Colour::Red),
+            mark(Mark::Circle(3), Colour::Yellow)
+        ];
+        let comment = to_comment(&annotations);
+        assert_eq!(parse_comment(&comment).unwrap(), (annotations.clone(), String::new()));
+        let around = format!("a fine move {} worth a look", comment);
+        assert_eq!(parse_comment(&around).unwrap(), (annotations, "a fine move worth a look".to_string()));
+        assert_eq!(parse_comment("no drawings").unwrap(), (vec![], "no drawings".to_string()));
+    }
+
+    #[test]
+    fn malformed_marks_are_rejected() {
+        assert!(parse_comment("[%draw purple e4]").is_err());
+        assert!(parse_comment("[%draw green z99]").is_err());
+        assert!(parse_comment("[%draw green e4-]").is_err());
+        assert!(parse_comment("[%draw green e4").is_err());
+    }
+
+    #[test]
+    fn drawing_a_mark_again_removes_or_recolours_it() {
+        let circle = Mark::Circle(45);
+        let arrow = Mark::Arrow(45, 46);
+        let mut annotations = vec![];
+        toggle(&mut annotations, mark(circle, Colour::Green));
+        toggle(&mut annotations, mark(arrow, Colour::Blue));
+        toggle(&mut annotations, mark(circle, Colour::Red));
+        assert_eq!(annotations, vec![mark(circle, Colour::Red), mark(arrow, Colour::Blue)]);
+        toggle(&mut annotations, mark(circle, Colour::Red));
+        assert_eq!(annotations, vec![mark(arrow, Colour::Blue)]);
+    }
+
+    #[test]
+    fn records_carry_their_drawings() {
+        let first = vec![mark(Mark::Circle(45), Colour::Green)];
+        let mut board = crate::board::Board::new();
+        let mut moves = vec![];
+        for _ in 0..2 {
+            let m = crate::logic::legal_moves(&board)[0];
+            crate::logic::make_move(&mut board, m);
+            moves.push(m);
+        }
+        let record = GameRecord {
+            moves,
+            comments: vec![
+                (1, to_comment(&first)),
+                (2, "White resigns".to_string())
+            ],
+            ..Default::default()
+        };
+        let read = crate::record::parse_records(&record.to_text()).unwrap();
+        let annotations = from_record(&read[0]).unwrap();
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[&1], first);
+    }
+}
@@ -1,37 +1,1443 @@
-use ggez;
+use std::collections::hash_map::{Entry, HashMap};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::Duration;
 use ggez::graphics;
 use ggez::graphics::Transform;
+use ggez::input::keyboard::{KeyCode, KeyMods};
 use ggez::input::mouse;
 use ggez::mint::Point2;
 use crate::board;
+use crate::eval::{self, EvalWeights, Material};
+use crate::game_line::{GameLine, Shown};
+use crate::glyphs;
+use crate::agent::Agent;
+use crate::animation::Animation;
+use crate::annotation::{self, Annotation, Mark};
+use crate::book::{Book, BookPlayer};
+use crate::level::{self, Level, Style, LEVELS, STYLES};
+use crate::puzzle::{self, Puzzle};
+use crate::tablebase::{self, Tablebase};
+use crate::logic::{self, Move, Outcome};
+use crate::record::{self, GameRecord};
+use crate::notation;
+use crate::overlay::{self, Armour, Heat};
+use crate::search::{self, Engine, EngineOptions, Limits, SearchInfo};
+use crate::geometry::COS_PI_6;
+use crate::see::{self, Warning};
+use crate::theme::{PieceSet, Theme, THEMES};
+use crate::view::View;
 
-pub const TILE_SIZE: f32 = 30.0;
-pub const BOARD_POS: [f32; 2] = [200.0, 70.0];
+// the window the GUI opens in, it can be resized down to MIN_WINDOW
+pub const WINDOW: [f32; 2] = [800.0, 600.0];
+pub const MIN_WINDOW: [f32; 2] = [640.0, 480.0];
+// the panels left of the board take PANEL_WIDTH, the board fits and is
+// centred in the rest less BOARD_MARGIN above and below; BOARD_TILES is
+// how many tiles across and down it needs with its labels
+pub const PANEL_WIDTH: f32 = 180.0;
+pub const BOARD_MARGIN: f32 = 20.0;
+pub const BOARD_TILES: [f32; 2] = [20.5, 18.5];
+// of the letters in the letters piece set, in tiles
+pub const PIECE_SIZE: f32 = 1.07;
+pub const COMPUTER_MOVETIME: Duration = Duration::from_millis(1500);
+pub const HINT_MOVETIME: Duration = Duration::from_millis(1000);
+pub const ANALYSIS_LINES: usize = 3;
+pub const DEFAULT_RECORD_FILE: &str = "games.txt";
+// the panel stacks the tablebase verdict, the options, the analysis with
+// its bar and the move list, PANEL_MARGIN in from the window's edges and
+// PANEL_GAP apart; the analysis takes up to ANALYSIS_ROWS lines and the
+// move list what is left
+pub const PANEL_MARGIN: f32 = 10.0;
+pub const PANEL_GAP: f32 = 8.0;
+pub const PANEL_TEXT: f32 = 10.0;
+pub const ANALYSIS_ROWS: f32 = 16.0;
+pub const EVAL_BAR_WIDTH: f32 = 14.0;
+// Topaz is monospaced, every character this share of the font size across
+pub const CHAR_WIDTH: f32 = 0.89;
+// the captured piece trays, in the top and bottom right corners of the
+// board's space: in tiles, how far in from its right edge they start and
+// between pieces, then how big the pieces are drawn
+pub const TRAY_START: f32 = 6.2;
+pub const TRAY_SPACING: f32 = 1.0;
+pub const TRAY_SCALE: f32 = 0.45;
+// the move list: the width of the move numbers, the height of a row and
+// the fewest rows the analysis leaves it
+pub const MOVE_NUMBER_WIDTH: f32 = 26.0;
+pub const MOVE_LIST_ROW: f32 = 12.0;
+pub const MOVE_LIST_MIN_ROWS: f32 = 4.0;
+// above the bottom of the window, leaving room for the typed move box
+pub const MOVE_LIST_BOTTOM: f32 = 48.0;
+// where the keyboard cursor starts, the middle of the board
+pub const CENTRE_HEX: usize = 45;
+
+#[derive(Clone, Debug, Default)]
+pub struct AppOptions {
+    // an external program speaking the engine protocol
+    pub engine_command: Option<String>,
+    // an opening book file for the computer
+    pub book: Option<String>,
+    // a directory of endgame tables, tablebase::DEFAULT_DIR when it exists
+    pub tablebases: Option<String>,
+    // a puzzle file for puzzle mode
+    pub puzzles: Option<String>,
+    // where games are saved, DEFAULT_RECORD_FILE when not given
+    pub record: Option<String>,
+    // a record file whose last game is opened to look through
+    pub open: Option<String>,
+    // for counting material, as eval::Material::parse reads them, the
+    // evaluation's values when not given
+    pub piece_values: Option<String>,
+    // one of theme::THEMES by name
+    pub theme: Option<String>,
+    // moves without animation
+    pub instant: bool
+}
 
 pub struct App {
     font: ggez::graphics::Font,
+    // the size of the window, the board and panels are laid out to fit it
+    window: [f32; 2],
     board: board::Board,
     state: AppState,
-    last_button: bool
+    last_button: bool,
+    computer: Option<board::Player>,
+    thinking: Option<Thinking>,
+    weights: EvalWeights,
+    computer_kind: ComputerKind,
+    // how hard and in what manner the computer plays
+    level: Level,
+    style: Style,
+    // taken by the worker thread while the computer is thinking
+    agent: Option<Box<dyn Agent>>,
+    options: AppOptions,
+    message: Option<String>,
+    book: Option<Arc<Book>>,
+    // the computer's last move came from the book
+    book_move: bool,
+    tablebase: Option<Arc<Tablebase>>,
+    puzzles: Vec<Puzzle>,
+    puzzle: Option<PuzzleProgress>,
+    // the engine's suggestion for the player to move
+    hint: Option<Move>,
+    hint_search: Option<HintSearch>,
+    // ask before playing a move that gives material away
+    blunder_check: bool,
+    warning: Option<Warning>,
+    // analysis mode searches the position on the board until it changes
+    analysing: bool,
+    analysis: Option<Analysis>,
+    // handed back by each analysis so the table carries over
+    analysis_engine: Option<Engine>,
+    analysis_info: Option<SearchInfo>,
+    theme: Theme,
+    piece_set: PieceSet,
+    // built in the theme's colours and the view's size the first time they are drawn
+    glyphs: HashMap<(board::Player, crate::pieces::Piece), graphics::Mesh>,
+    view: View,
+    move_input: MoveInput,
+    drag: Option<Drag>,
+    // the game's moves, any variation from it and their drawings
+    line: GameLine<AppState>,
+    heat: Heat,
+    piece_values: Material,
+    // rings round fortresses, armoured or breached, and undefended pieces
+    markers: bool,
+    // a right button press waiting to be let go
+    annotating: Option<(usize, annotation::Colour)>,
+    // moves still being shown, unless they are shown instantly
+    animations: Vec<Animation>,
+    instant: bool,
+    // the hex the keyboard is on
+    cursor: Option<usize>,
+    // a move being typed in
+    typed: Option<String>
+}
+
+struct Analysis {
+    key: u64,
+    stop: Arc<AtomicBool>,
+    updates: mpsc::Receiver<SearchInfo>,
+    handle: JoinHandle<Engine>
+}
+
+struct HintSearch {
+    stop: Arc<AtomicBool>,
+    result: mpsc::Receiver<Option<Move>>
+}
+
+// where the parts of the panel left of the board go
+struct Panels {
+    verdict: [f32; 2],
+    options: [f32; 2],
+    // the analysis text, and the bar beside it
+    analysis: graphics::Rect,
+    bar: graphics::Rect,
+    // from the top of the first row to the bottom of the last
+    move_list: (f32, f32),
+    // the x of move numbers, White's moves and Black's, and the right edge
+    columns: [f32; 4]
+}
+
+// a piece held under the pointer
+#[derive(Clone, Copy)]
+struct Drag {
+    from: usize,
+    // it was already selected when it was picked up, so a click lets go of it
+    was_selected: bool
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MoveInput {
+    // click a piece, then its target
+    Click,
+    // drag a piece to its target, clicking works too
+    Drag
+}
+
+// how far into the current puzzle the player has got
+struct PuzzleProgress {
+    index: usize,
+    // moves played so far, both sides
+    step: usize,
+    // false once the player has found another way to the same win
+    on_line: bool,
+    solved: bool
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ComputerKind {
+    AlphaBeta,
+    Mcts,
+    External
+}
+
+type AgentFactory = Box<dyn FnOnce() -> Result<Box<dyn Agent>, String> + Send>;
+type AgentReply = Result<(Box<dyn Agent>, Option<Move>), String>;
+
+struct Thinking {
+    stop: Arc<AtomicBool>,
+    result: mpsc::Receiver<AgentReply>
 }
 
 #[derive(Clone, Copy, Debug)]
 pub enum AppState {
     Waiting(board::Player),
     SelectedPiece(board::Player, usize),
+    Confirming(board::Player, Move),
+    Thinking(board::Player),
     GameWonBy(board::Player)
 }
 
 impl App {
-    pub fn new(ctx: &mut ggez::Context) -> App {
+    pub fn new(ctx: &mut ggez::Context, options: AppOptions) -> App {
         let font = ggez::graphics::Font::new(ctx, "/Topaz-8.ttf").unwrap();
-        App {
+        let weights = EvalWeights::load().unwrap_or_else(|e| {
+            eprintln!("using default evaluation weights: {}", e);
+            EvalWeights::default()
+        });
+        let (book, message) = match &options.book {
+            Some(path) => match Book::load(path) {
+                Ok(book) => (Some(Arc::new(book)), None),
+                Err(e) => (None, Some(e))
+            },
+            None => (None, None)
+        };
+        let tablebase_dir = options.tablebases.clone()
+            .or_else(|| Some(tablebase::DEFAULT_DIR.to_string()).filter(|dir| Path::new(dir).is_dir()));
+        let (tablebase, message) = match tablebase_dir.map(|dir| Tablebase::load_dir(Path::new(&dir))) {
+            Some(Ok(tablebase)) => (Some(Arc::new(tablebase)), message),
+            Some(Err(e)) => (None, message.or(Some(e))),
+            None => (None, message)
+        };
+        let (puzzles, message) = match options.puzzles.as_deref().map(puzzle::load) {
+            Some(Ok(puzzles)) => (puzzles, message),
+            Some(Err(e)) => (vec![], message.or(Some(e))),
+            None => (vec![], message)
+        };
+        let (piece_values, message) = match options.piece_values.as_deref().map(Material::parse) {
+            Some(Ok(values)) => (values, message),
+            Some(Err(e)) => (weights.material.clone(), message.or(Some(e))),
+            None => (weights.material.clone(), message)
+        };
+        let (theme, message) = match options.theme.as_deref().map(Theme::parse) {
+            Some(Ok(theme)) => (theme, message),
+            Some(Err(e)) => (THEMES[0], message.or(Some(e))),
+            None => (THEMES[0], message)
+        };
+        let instant = options.instant;
+        let screen = graphics::screen_coordinates(ctx);
+        let mut app = App {
             state: AppState::Waiting(board::Player::White),
             last_button: mouse::button_pressed(ctx, mouse::MouseButton::Left),
             font,
-            board: board::Board::new()
+            window: [screen.w, screen.h],
+            board: board::Board::new(),
+            computer: None,
+            thinking: None,
+            weights,
+            computer_kind: ComputerKind::AlphaBeta,
+            level: level::FULL_STRENGTH,
+            style: Style::Balanced,
+            agent: None,
+            options,
+            message,
+            book,
+            book_move: false,
+            tablebase,
+            puzzles,
+            puzzle: None,
+            hint: None,
+            hint_search: None,
+            blunder_check: false,
+            warning: None,
+            analysing: false,
+            analysis: None,
+            analysis_engine: None,
+            analysis_info: None,
+            theme,
+            piece_set: PieceSet::Shapes,
+            glyphs: HashMap::new(),
+            view: View::default(),
+            move_input: MoveInput::Drag,
+            drag: None,
+            line: GameLine::default(),
+            heat: Heat::Off,
+            piece_values,
+            markers: false,
+            annotating: None,
+            animations: vec![],
+            instant,
+            cursor: None,
+            typed: None
+        };
+        if let Some(path) = app.options.open.clone() {
+            if let Err(e) = app.open_game(&path) {
+                app.message = Some(e);
+            }
+        }
+        app
+    }
+
+    fn play_move(&mut self, player: board::Player, m: Move) {
+        self.clear_hint();
+        let animation = Animation::new(&self.board, m).filter(|_| !self.instant);
+        if self.line.next_move() == Some(m) {
+            self.step_forward();
+            self.animate(animation);
+            return;
+        }
+        self.animate(animation);
+        self.line.play(&self.board, m);
+        let target = crate::logic::make_move(&mut self.board, m);
+        match target {
+            Some((_, crate::pieces::Piece::King)) => {
+                self.state = AppState::GameWonBy(player)
+            },
+            _ => {
+                self.state = AppState::Waiting(player.opposite());
+            }
+        }
+    }
+
+    // one move's animation ends any still showing the pieces it moves
+    fn animate(&mut self, animation: Option<Animation>) {
+        if let Some(animation) = animation {
+            self.animations.retain(|other| other.to != animation.from && other.to != animation.to);
+            self.animations.push(animation);
+        }
+    }
+
+    fn clear_hint(&mut self) {
+        self.hint = None;
+        if let Some(search) = self.hint_search.take() {
+            search.stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn request_hint(&mut self) {
+        let player = match self.state {
+            AppState::Waiting(player) | AppState::SelectedPiece(player, _) => player,
+            _ => return
+        };
+        if self.computer == Some(player) || self.hint.is_some() || self.hint_search.is_some() {
+            return;
+        }
+        let (sender, result) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let board = self.board.clone();
+        let weights = self.weights.clone();
+        let tablebase = self.tablebase.clone();
+        std::thread::spawn(move || {
+            let mut engine = Engine::new(weights);
+            engine.set_tablebase(tablebase);
+            let limits = Limits {
+                movetime: Some(HINT_MOVETIME),
+                ..Default::default()
+            };
+            let m = engine.search(&board, limits, &thread_stop, |_| {}).and_then(|info| info.best_move());
+            let _ = sender.send(m);
+        });
+        self.hint_search = Some(HintSearch { stop, result });
+    }
+
+    fn toggle_analysis(&mut self) {
+        self.analysing = !self.analysing;
+        if self.analysing {
+            // both sides are moved by hand while analysing
+            self.computer = None;
+            self.stop_thinking();
+            if let AppState::Thinking(player) = self.state {
+                self.state = AppState::Waiting(player);
+            }
+        } else {
+            self.stop_analysis();
+        }
+    }
+
+    fn stop_analysis(&mut self) {
+        if let Some(analysis) = self.analysis.take() {
+            analysis.stop.store(true, Ordering::Relaxed);
+            // the search stops within a few nodes
+            self.analysis_engine = analysis.handle.join().ok();
+        }
+        self.analysis_info = None;
+    }
+
+    // called every frame, restarts the search when the board has changed
+    fn update_analysis(&mut self) {
+        if !self.analysing {
+            return;
+        }
+        let key = self.board.hash();
+        if self.analysis.as_ref().map(|analysis| analysis.key) != Some(key) {
+            self.stop_analysis();
+            if logic::winner(&self.board).is_none() {
+                self.start_analysis(key);
+            }
+        }
+        if let Some(info) = self.analysis.as_ref().and_then(|analysis| analysis.updates.try_iter().last()) {
+            self.analysis_info = Some(info);
+        }
+    }
+
+    fn start_analysis(&mut self, key: u64) {
+        let mut engine = self.analysis_engine.take().unwrap_or_else(|| {
+            let options = EngineOptions {
+                multi_pv: ANALYSIS_LINES,
+                ..Default::default()
+            };
+            let mut engine = Engine::with_options(self.weights.clone(), options);
+            engine.set_tablebase(self.tablebase.clone());
+            engine
+        });
+        let (sender, updates) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let board = self.board.clone();
+        let handle = std::thread::spawn(move || {
+            engine.search(&board, Limits::default(), &thread_stop, |info| {
+                let _ = sender.send(info.clone());
+            });
+            engine
+        });
+        self.analysis = Some(Analysis { key, stop, updates, handle });
+    }
+
+    fn own_piece(&self, player: board::Player, hex: Option<usize>) -> Option<usize> {
+        hex.filter(|hex| matches!(self.board.hexes[*hex], Some((colour, _)) if colour == player))
+    }
+
+    // where the piece on hex can move to
+    fn targets(&self, hex: usize) -> Vec<usize> {
+        let mut valid_moves = logic::find_valid_moves(&self.board, hex);
+        valid_moves.retain(|h| logic::is_vulnerable(&self.board, *h));
+        valid_moves
+    }
+
+    // a click on hex, or enter with the cursor on it
+    fn choose_hex(&mut self, hex: usize) {
+        match self.state {
+            AppState::Waiting(player) if self.computer != Some(player) && self.own_piece(player, Some(hex)).is_some() => {
+                self.state = AppState::SelectedPiece(player, hex);
+            },
+            AppState::SelectedPiece(player, selected) => {
+                if hex == selected {
+                    self.state = AppState::Waiting(player);
+                } else if self.targets(selected).contains(&hex) {
+                    self.try_move(player, Move { from: selected, to: hex });
+                } else if self.own_piece(player, Some(hex)).is_some() {
+                    self.state = AppState::SelectedPiece(player, hex);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn pick_up(&mut self, player: board::Player, hex: usize, selected: Option<usize>) {
+        self.drag = Some(Drag { from: hex, was_selected: selected == Some(hex) });
+        self.state = AppState::SelectedPiece(player, hex);
+    }
+
+    // a piece let go where it started is a click, anywhere but a target
+    // puts it back
+    fn drop_piece(&mut self, player: board::Player, drag: Drag, hex: Option<usize>) {
+        self.drag = None;
+        match hex {
+            Some(hex) if hex == drag.from => {
+                if drag.was_selected {
+                    self.state = AppState::Waiting(player);
+                }
+            },
+            Some(hex) if self.targets(drag.from).contains(&hex) => {
+                self.try_move(player, Move { from: drag.from, to: hex });
+                if let Some(animation) = self.animations.last_mut().filter(|animation| animation.from == drag.from) {
+                    animation.dropped();
+                }
+            },
+            _ => self.state = AppState::Waiting(player)
+        }
+    }
+
+    fn toggle_move_input(&mut self) {
+        self.move_input = match self.move_input {
+            MoveInput::Click => MoveInput::Drag,
+            MoveInput::Drag => MoveInput::Click
+        };
+        self.drag = None;
+    }
+
+    // moves that give something away wait for confirmation first
+    fn try_move(&mut self, player: board::Player, m: Move) {
+        if self.blunder_check && self.puzzle.is_none() {
+            if let Some(warning) = see::blunder_warning(&self.board, m) {
+                self.warning = Some(warning);
+                self.state = AppState::Confirming(player, m);
+                return;
+            }
+        }
+        self.human_move(player, m);
+    }
+
+    fn confirm_move(&mut self, confirmed: bool) {
+        if let AppState::Confirming(player, m) = self.state {
+            self.warning = None;
+            if confirmed {
+                self.human_move(player, m);
+            } else {
+                self.state = AppState::SelectedPiece(player, m.from);
+            }
+        }
+    }
+
+    fn human_move(&mut self, player: board::Player, m: Move) {
+        self.book_move = false;
+        if self.puzzle.is_some() {
+            self.puzzle_move(player, m);
+        } else {
+            self.play_move(player, m);
+        }
+    }
+
+    fn next_puzzle(&mut self) {
+        if self.puzzles.is_empty() {
+            self.message = Some("No puzzles, start with --puzzles FILE".to_string());
+            return;
+        }
+        self.stop_thinking();
+        let index = self.puzzle.as_ref().map_or(0, |progress| (progress.index + 1) % self.puzzles.len());
+        self.board = self.puzzles[index].board.clone();
+        self.line.clear();
+        self.animations.clear();
+        self.clear_hint();
+        self.computer = None;
+        self.book_move = false;
+        self.message = None;
+        self.state = AppState::Waiting(self.board.current_turn);
+        self.puzzle = Some(PuzzleProgress { index, step: 0, on_line: true, solved: false });
+    }
+
+    fn puzzle_move(&mut self, player: board::Player, m: Move) {
+        if let Some(mut progress) = self.puzzle.take() {
+            self.advance_puzzle(&mut progress, player, m);
+            self.puzzle = Some(progress);
+        }
+    }
+
+    // wrong moves are taken back, right ones are answered at once
+    fn advance_puzzle(&mut self, progress: &mut PuzzleProgress, player: board::Player, m: Move) {
+        if progress.solved {
+            self.play_move(player, m);
+            return;
+        }
+        let puzzle = self.puzzles[progress.index].clone();
+        if !puzzle.accepts(&self.board, progress.step, m) {
+            self.message = Some("Wrong move, try again".to_string());
+            self.state = AppState::Waiting(player);
+            return;
+        }
+        self.message = None;
+        progress.on_line &= puzzle.line.get(progress.step) == Some(&m);
+        progress.step += 1;
+        self.play_move(player, m);
+        if let AppState::GameWonBy(_) = self.state {
+            progress.solved = true;
+            return;
+        }
+        let reply = if progress.on_line {
+            puzzle.line.get(progress.step).cloned()
+        } else {
+            puzzle.mate_in().and_then(|n| crate::solver::longest_defence(&self.board, n - progress.step as u32 / 2))
+        };
+        match reply {
+            Some(reply) => {
+                progress.step += 1;
+                self.play_move(player.opposite(), reply);
+            },
+            None => progress.solved = true
+        }
+    }
+
+    fn stop_thinking(&mut self) {
+        if let Some(thinking) = self.thinking.take() {
+            thinking.stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // back to the last position a person is to move in, a resignation is
+    // taken back on its own
+    fn undo(&mut self) {
+        if self.puzzle.is_some() {
+            self.message = Some("No taking moves back in a puzzle".to_string());
+            return;
+        }
+        self.stop_thinking();
+        self.go_to(self.line.end_ply());
+        let resigned = matches!(self.state, AppState::GameWonBy(_)) && logic::winner(&self.board).is_none();
+        if !resigned {
+            match self.line.take_back() {
+                Some(board) => self.board = board,
+                None => return
+            }
+            while self.computer == Some(self.board.current_turn) {
+                match self.line.take_back() {
+                    Some(board) => self.board = board,
+                    None => break
+                }
+            }
+        }
+        self.clear_hint();
+        self.animations.clear();
+        self.warning = None;
+        self.message = None;
+        self.book_move = false;
+        self.state = AppState::Waiting(self.board.current_turn);
+    }
+
+    fn new_game(&mut self) {
+        self.stop_thinking();
+        self.board = board::Board::new();
+        self.line.clear();
+        self.animations.clear();
+        self.puzzle = None;
+        self.clear_hint();
+        self.warning = None;
+        self.message = None;
+        self.book_move = false;
+        self.state = AppState::Waiting(self.board.current_turn);
+    }
+
+    // the player at the bottom gives up
+    fn resign(&mut self) {
+        if let AppState::GameWonBy(_) = self.state {
+            return;
+        }
+        let loser = self.bottom();
+        self.stop_thinking();
+        self.warning = None;
+        self.message = Some(format!("{:?} resigns", loser));
+        self.state = AppState::GameWonBy(loser.opposite());
+    }
+
+    fn game_record(&self) -> GameRecord {
+        let (end, state) = match self.line.end() {
+            Some((board, state)) => (board, *state),
+            None => (&self.board, self.state)
+        };
+        let line = self.line.moves();
+        let start = line.first().map_or(end, |(board, _)| board);
+        let mut record = GameRecord {
+            moves: line.iter().map(|(_, m)| *m).collect(),
+            ..Default::default()
+        };
+        for player in [board::Player::White, board::Player::Black] {
+            let name = match self.computer {
+                Some(computer) if computer == player => format!("{:?}", self.computer_kind),
+                _ => "human".to_string()
+            };
+            record.set_tag(&format!("{:?}", player), &name);
+        }
+        if notation::position_string(start) != notation::START_POSITION {
+            record.set_tag("Start", &notation::position_string(start));
+        }
+        record.result = match state {
+            AppState::GameWonBy(player) => Some(Outcome::Won(player)),
+            _ => logic::outcome(end)
+        };
+        let annotations = &self.line.annotations;
+        let mut plies: Vec<&usize> = annotations.keys().collect();
+        plies.sort();
+        for ply in plies {
+            if !annotations[ply].is_empty() {
+                record.comments.push((*ply, annotation::to_comment(&annotations[ply])));
+            }
+        }
+        if let AppState::GameWonBy(winner) = state {
+            if logic::winner(end).is_none() {
+                record.comments.push((line.len(), format!("{:?} resigns", winner.opposite())));
+            }
+        }
+        record
+    }
+
+    // the position after ply moves of the game on the board
+    fn go_to(&mut self, ply: usize) {
+        if ply != self.line.ply() && self.puzzle.as_ref().is_some_and(|progress| !progress.solved) {
+            self.message = Some("No looking back in a puzzle".to_string());
+            return;
+        }
+        while self.line.ply() > ply && self.step_back() {}
+        while self.line.ply() < ply && self.step_forward() {}
+    }
+
+    fn step_back(&mut self) -> bool {
+        if self.line.ply() == 0 {
+            return false;
+        }
+        if !self.line.looking_back() {
+            self.stop_thinking();
+        }
+        let state = match self.state {
+            AppState::Thinking(player) | AppState::SelectedPiece(player, _) | AppState::Confirming(player, _) => AppState::Waiting(player),
+            state => state
+        };
+        match self.line.step_back(&self.board, state) {
+            Some(board) => self.show(Shown::Earlier(board)),
+            None => return false
+        }
+        true
+    }
+
+    fn step_forward(&mut self) -> bool {
+        match self.line.step_forward() {
+            Some(shown) => self.show(shown),
+            None => return false
+        }
+        true
+    }
+
+    // from a variation back to where it left the game
+    fn back_to_game(&mut self) {
+        if !self.line.in_variation() {
+            return;
+        }
+        self.stop_thinking();
+        if let Some(shown) = self.line.back_to_game() {
+            self.show(shown);
+        }
+        self.message = None;
+    }
+
+    fn show(&mut self, shown: Shown<AppState>) {
+        match shown {
+            Shown::Earlier(board) => {
+                self.board = board;
+                self.state = AppState::Waiting(self.board.current_turn);
+            },
+            Shown::End(board, state) => {
+                self.board = board;
+                self.state = state;
+            }
+        }
+        self.left_position();
+    }
+
+    fn left_position(&mut self) {
+        self.clear_hint();
+        self.animations.clear();
+        self.warning = None;
+        self.book_move = false;
+    }
+
+    // the last game in a record file, at its end and with its drawings
+    fn open_game(&mut self, path: &str) -> Result<(), String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let records = record::parse_records(&text)?;
+        let record = records.last().ok_or_else(|| format!("{}: no games", path))?;
+        let boards = record.boards()?;
+        let annotations = annotation::from_record(record)?;
+        self.new_game();
+        for (board, m) in boards.iter().zip(&record.moves) {
+            self.line.play(board, *m);
+        }
+        self.board = boards[record.moves.len()].clone();
+        self.line.annotations = annotations;
+        self.state = match record.result {
+            Some(Outcome::Won(player)) => AppState::GameWonBy(player),
+            _ => AppState::Waiting(self.board.current_turn)
+        };
+        Ok(())
+    }
+
+    // added to the end of the record file
+    fn save_game(&mut self) {
+        let path = self.options.record.clone().unwrap_or_else(|| DEFAULT_RECORD_FILE.to_string());
+        let text = self.game_record().to_text();
+        let saved = std::fs::OpenOptions::new().create(true).append(true).open(&path)
+            .and_then(|mut file| {
+                let separator = if file.metadata()?.len() > 0 { "\n" } else { "" };
+                write!(file, "{}{}", separator, text)
+            });
+        self.message = Some(match saved {
+            Ok(()) => format!("Saved the game to {}", path),
+            Err(e) => format!("{}: {}", path, e)
+        });
+    }
+
+    // direction is on screen, in degrees clockwise from right
+    fn move_cursor(&mut self, direction: f32) {
+        let start = match self.state {
+            AppState::SelectedPiece(_, selected) => selected,
+            _ => CENTRE_HEX
+        };
+        let cursor = match self.cursor {
+            Some(cursor) => cursor,
+            None => {
+                self.cursor = Some(start);
+                return;
+            }
+        };
+        if let Some(hex) = self.view.step(&self.board, self.bottom(), cursor, direction) {
+            self.cursor = Some(hex);
+        }
+    }
+
+    fn enter(&mut self) {
+        match (self.state, self.cursor) {
+            (AppState::Confirming(..), _) => self.confirm_move(true),
+            (_, Some(cursor)) => self.choose_hex(cursor),
+            _ => {}
+        }
+    }
+
+    // keys while a move is being typed
+    fn type_key(&mut self, keycode: KeyCode) {
+        match keycode {
+            KeyCode::Return | KeyCode::NumpadEnter => self.play_typed(),
+            KeyCode::Back => {
+                if let Some(typed) = self.typed.as_mut() {
+                    typed.pop();
+                }
+            },
+            KeyCode::Escape => self.typed = None,
+            _ => {}
+        }
+    }
+
+    fn play_typed(&mut self) {
+        let text = self.typed.take().unwrap_or_default();
+        let player = match self.state {
+            AppState::Waiting(player) | AppState::SelectedPiece(player, _) if self.computer != Some(player) => player,
+            _ => {
+                self.message = Some("It's not your move".to_string());
+                return;
+            }
+        };
+        match notation::parse_described_move(&self.board, &text) {
+            Ok(m) => {
+                self.message = None;
+                self.try_move(player, m);
+            },
+            Err(e) => self.message = Some(e)
+        }
+    }
+
+    // off -> computer plays black -> computer plays white -> off
+    fn cycle_computer(&mut self) {
+        self.puzzle = None;
+        if self.analysing {
+            self.toggle_analysis();
+        }
+        self.computer = match self.computer {
+            None => Some(board::Player::Black),
+            Some(board::Player::Black) => Some(board::Player::White),
+            Some(board::Player::White) => None
+        };
+        self.stop_thinking();
+        match self.state {
+            AppState::Thinking(player) | AppState::SelectedPiece(player, _) | AppState::Confirming(player, _) => {
+                self.warning = None;
+                self.state = AppState::Waiting(player);
+            },
+            _ => {}
+        }
+    }
+
+    fn switch_computer_kind(&mut self) {
+        self.computer_kind = match self.computer_kind {
+            ComputerKind::AlphaBeta => ComputerKind::Mcts,
+            ComputerKind::Mcts if self.options.engine_command.is_some() => ComputerKind::External,
+            _ => ComputerKind::AlphaBeta
+        };
+        self.reset_agent();
+    }
+
+    fn cycle_level(&mut self) {
+        let index = LEVELS.iter().position(|level| *level == self.level).unwrap_or(0);
+        self.level = LEVELS[(index + 1) % LEVELS.len()];
+        self.reset_agent();
+    }
+
+    fn cycle_style(&mut self) {
+        let index = STYLES.iter().position(|style| *style == self.style).unwrap_or(0);
+        self.style = STYLES[(index + 1) % STYLES.len()];
+        self.reset_agent();
+    }
+
+    fn cycle_theme(&mut self) {
+        self.theme = self.theme.next();
+        self.glyphs.clear();
+    }
+
+    // the side that sits at the bottom when the board is not flipped, the
+    // player to move in hot-seat games and the human against the computer
+    fn bottom(&self) -> board::Player {
+        match self.computer {
+            Some(computer) => computer.opposite(),
+            None => self.board.current_turn
+        }
+    }
+
+    // as big as fits the space the panels leave
+    fn tile_size(&self) -> f32 {
+        let width = (self.window[0] - PANEL_WIDTH) / BOARD_TILES[0];
+        let height = (self.window[1] - 2.0 * BOARD_MARGIN) / BOARD_TILES[1];
+        width.min(height) * self.view.scale()
+    }
+
+    fn board_centre(&self) -> [f32; 2] {
+        [(PANEL_WIDTH + self.window[0]) / 2.0, self.window[1] / 2.0]
+    }
+
+    fn verdict_text(&self) -> Option<String> {
+        let probe = self.tablebase.as_ref()?.probe(&self.board)?;
+        Some(format!("Tablebase: {}", probe.describe(self.board.current_turn)))
+    }
+
+    fn options_text(&self) -> String {
+        let hint_msg = match (self.hint, &self.hint_search) {
+            (Some(m), _) => format!("Hint: {}", notation::describe_move(&self.board, m)),
+            (None, Some(_)) => "Hint: thinking...".to_string(),
+            (None, None) => "H for a hint".to_string()
+        };
+        format!(
+            "{}\nB: blunder check {}\nL: level {}\nS: style {}\nT: theme {}\nG: pieces {}\nD: {}\nI: heat map {}\nK: fortress marks {}\nQ: moves {}",
+            hint_msg, if self.blunder_check { "on" } else { "off" }, self.level.name, self.style.name(),
+            self.theme.name, self.piece_set.name(),
+            match self.move_input { MoveInput::Click => "click to move", MoveInput::Drag => "drag to move" },
+            self.heat.name(), if self.markers { "on" } else { "off" },
+            if self.instant { "instant" } else { "animated" }
+        )
+    }
+
+    // down the panel from the top, each part as tall as its text wraps to
+    fn panels(&self) -> Panels {
+        let width = PANEL_WIDTH - 2.0 * PANEL_MARGIN;
+        let mut y = PANEL_MARGIN;
+        let verdict = [PANEL_MARGIN, y];
+        if let Some(text) = self.verdict_text() {
+            y += wrapped_lines(&text, PANEL_TEXT, width) as f32 * PANEL_TEXT + PANEL_GAP;
+        }
+        let options = [PANEL_MARGIN, y];
+        y += wrapped_lines(&self.options_text(), PANEL_TEXT, width) as f32 * PANEL_TEXT + PANEL_GAP;
+        let bottom = self.window[1] - MOVE_LIST_BOTTOM;
+        let bar_x = PANEL_WIDTH - PANEL_MARGIN - EVAL_BAR_WIDTH;
+        let mut analysis = graphics::Rect::new(PANEL_MARGIN, y, bar_x - PANEL_GAP - PANEL_MARGIN, 0.0);
+        if self.analysing {
+            let room = bottom - y - MOVE_LIST_MIN_ROWS * MOVE_LIST_ROW - PANEL_GAP;
+            analysis.h = (ANALYSIS_ROWS * PANEL_TEXT).min(room).max(PANEL_TEXT);
+            y += analysis.h + PANEL_GAP;
+        }
+        let bar = graphics::Rect::new(bar_x, analysis.y, EVAL_BAR_WIDTH, analysis.h);
+        if self.line.in_variation() {
+            y += MOVE_LIST_ROW;
+        }
+        let moves = PANEL_MARGIN + MOVE_NUMBER_WIDTH;
+        let move_width = (PANEL_WIDTH - PANEL_MARGIN - moves) / 2.0;
+        Panels {
+            verdict,
+            options,
+            analysis,
+            bar,
+            move_list: (y, bottom.max(y + MOVE_LIST_ROW)),
+            columns: [PANEL_MARGIN, moves, moves + move_width, moves + 2.0 * move_width]
         }
     }
+
+    // a point relative to the middle of the board in tiles, as layout gives
+    // them, in pixels
+    fn to_screen(&self, pos: [f32; 2]) -> [f32; 2] {
+        let centre = self.board_centre();
+        let tile_size = self.tile_size();
+        [centre[0] + pos[0] * tile_size, centre[1] + pos[1] * tile_size]
+    }
+
+    fn layout(&self) -> [[f32; 2]; board::HEX_COUNT] {
+        self.view.layout(&self.board, self.bottom())
+    }
+
+    fn pick_hex(&self, layout: &[[f32; 2]], pointer_pos: Point2<f32>) -> Option<usize> {
+        let centre = self.board_centre();
+        let pick_x = (pointer_pos.x - centre[0]) / self.tile_size();
+        let pick_y = (pointer_pos.y - centre[1]) / self.tile_size();
+        hex_pick([pick_x, pick_y], layout)
+    }
+
+    fn toggle_rotation(&mut self) {
+        self.view.rotated = !self.view.rotated;
+        self.glyphs.clear();
+    }
+
+    fn cycle_piece_set(&mut self) {
+        self.piece_set = self.piece_set.next();
+    }
+
+    // the next move is thought about by a freshly made agent
+    fn reset_agent(&mut self) {
+        self.agent = None;
+        self.stop_thinking();
+        if let AppState::Thinking(player) = self.state {
+            self.state = AppState::Waiting(player);
+        }
+    }
+
+    // agents are created on the worker thread, launching a program can be slow
+    fn agent_factory(&self) -> AgentFactory {
+        let factory = self.player_factory();
+        match self.book.clone() {
+            Some(book) => Box::new(move || {
+                factory().map(|player| Box::new(BookPlayer::new(book, player)) as Box<dyn Agent>)
+            }),
+            None => factory
+        }
+    }
+
+    fn player_factory(&self) -> AgentFactory {
+        match self.computer_kind {
+            ComputerKind::AlphaBeta => {
+                let weights = self.weights.clone();
+                let tablebase = self.tablebase.clone();
+                let (level, style) = (self.level, self.style);
+                Box::new(move || {
+                    let limits = crate::search::Limits {
+                        movetime: Some(COMPUTER_MOVETIME),
+                        ..Default::default()
+                    };
+                    let mut player = crate::agent::AlphaBeta::new(weights.clone(), limits);
+                    player.engine.set_tablebase(tablebase);
+                    player.set_level(level);
+                    player.set_style(style, &weights);
+                    Ok(Box::new(player) as Box<dyn Agent>)
+                })
+            },
+            ComputerKind::Mcts => {
+                Box::new(|| {
+                    let options = crate::mcts::MctsOptions {
+                        movetime: Some(COMPUTER_MOVETIME),
+                        ..Default::default()
+                    };
+                    Ok(Box::new(crate::mcts::Mcts::new(options)) as Box<dyn Agent>)
+                })
+            },
+            ComputerKind::External => {
+                let command = self.options.engine_command.clone().unwrap_or_default();
+                let (level, style) = (self.level, self.style);
+                Box::new(move || {
+                    let mut engine = crate::protocol::ExternalEngine::launch(&command, COMPUTER_MOVETIME)?;
+                    // engines without these options just say so and play on
+                    engine.set_option("Level", level.name)?;
+                    engine.set_option("Style", style.name())?;
+                    Ok(Box::new(engine) as Box<dyn Agent>)
+                })
+            }
+        }
+    }
+
+    fn start_thinking(&mut self, player: board::Player) {
+        let (sender, result) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let board = self.board.clone();
+        let thread_stop = stop.clone();
+        // the agent comes back with the move so it keeps what it learned
+        let agent = self.agent.take();
+        let factory = self.agent_factory();
+        std::thread::spawn(move || {
+            let agent = match agent {
+                Some(agent) => Ok(agent),
+                None => factory()
+            };
+            let reply = agent.and_then(|mut agent| {
+                let m = agent.choose_move(&board, &thread_stop)?;
+                Ok((agent, m))
+            });
+            let _ = sender.send(reply);
+        });
+        self.thinking = Some(Thinking { stop, result });
+        self.state = AppState::Thinking(player);
+    }
+}
+
+impl App {
+    // scale shrinks it from the size it has on the board, alpha fades it
+    fn draw_piece(&mut self, ctx: &mut ggez::Context, player: board::Player, piece: crate::pieces::Piece, dest: [f32; 2], scale: f32, alpha: f32) -> ggez::GameResult<()> {
+        let (mut fill, mut outline) = self.theme.piece(player);
+        let tile_size = self.tile_size();
+        match self.piece_set {
+            PieceSet::Shapes => {
+                let mesh = match self.glyphs.entry((player, piece)) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(glyphs::piece_mesh(ctx, piece, tile_size * 0.75, fill, outline)?)
+                };
+                let params = graphics::DrawParam::default().dest(dest).scale([scale, scale]).color(graphics::Color::new(1.0, 1.0, 1.0, alpha));
+                graphics::draw(ctx, mesh, params)
+            },
+            PieceSet::Letters => {
+                fill.a *= alpha;
+                outline.a *= alpha;
+                glyphs::draw_letter(ctx, piece, self.font, PIECE_SIZE * tile_size * scale, dest, fill, outline)
+            }
+        }
+    }
+
+    // the taken piece fading where the mover arrives, and a promoting pawn
+    // fading into its queen
+    fn draw_animations(&mut self, ctx: &mut ggez::Context, layout: &[[f32; 2]]) -> ggez::GameResult<()> {
+        let bottom = self.bottom();
+        for animation in self.animations.clone() {
+            let t = animation.progress();
+            if let Some((player, piece)) = animation.captured {
+                let pos = layout[animation.to];
+                self.draw_piece(ctx, player, piece, self.to_screen(pos), 1.0, 1.0 - t)?;
+            }
+            let pos = self.view.place(animation.position(), bottom);
+            let dest = self.to_screen([pos[0], pos[1] - animation.lift()]);
+            match animation.promotion {
+                Some(promotion) => {
+                    self.draw_piece(ctx, animation.player, animation.piece, dest, 1.0, 1.0 - t)?;
+                    self.draw_piece(ctx, animation.player, promotion, dest, 1.0, t)?;
+                },
+                None => self.draw_piece(ctx, animation.player, animation.piece, dest, 1.0, 1.0)?
+            }
+        }
+        Ok(())
+    }
+
+    // rank numbers at both ends of each rank, and file letters where each
+    // file runs off the bottom of the board
+    fn draw_labels(&self, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        let bottom = self.bottom();
+        let positions = &self.board.positions;
+        let mut labels = vec![];
+        let mut first = 0;
+        for (rank, length) in board::RANK_LENGTHS.iter().enumerate() {
+            let number = (board::RANK_LENGTHS.len() - rank).to_string();
+            let (left, right) = (positions[first], positions[first + length - 1]);
+            labels.push((number.clone(), [left[0] - 1.8 * COS_PI_6, left[1]]));
+            labels.push((number, [right[0] + 1.8 * COS_PI_6, right[1]]));
+            first += length;
+        }
+        for file in 0..board::RANK_LENGTHS[board::RANK_LENGTHS.len() / 2] {
+            if let Some(hex) = (0..board::HEX_COUNT).rev().find(|hex| board::get_file(*hex) == file) {
+                let letter = ((b'a' + file as u8) as char).to_string();
+                labels.push((letter, [positions[hex][0] + 0.9 * COS_PI_6, positions[hex][1] + 1.35]));
+            }
+        }
+        for (text, pos) in labels {
+            let pos = self.view.place(pos, bottom);
+            let text = graphics::Text::new((text, self.font, 12.0));
+            let dimensions = text.dimensions(ctx);
+            let [x, y] = self.to_screen(pos);
+            let dest = [x - dimensions.w / 2.0, y - dimensions.h / 2.0];
+            graphics::draw(ctx, &text, (dest, self.theme.text))?;
+        }
+        Ok(())
+    }
+
+    fn draw_annotations(&self, ctx: &mut ggez::Context, layout: &[[f32; 2]]) -> ggez::GameResult<()> {
+        let annotations = match self.line.annotations.get(&self.line.ply()) {
+            Some(annotations) if !annotations.is_empty() => annotations,
+            _ => return Ok(())
+        };
+        let tile_size = self.tile_size();
+        let screen = |hex: usize| self.to_screen(layout[hex]);
+        let mut builder = graphics::MeshBuilder::new();
+        for annotation in annotations {
+            let [r, g, b] = annotation.colour.rgb();
+            let colour = graphics::Color::new(r, g, b, 0.8);
+            match annotation.mark {
+                Mark::Circle(hex) => {
+                    builder.circle(graphics::DrawMode::stroke(tile_size * 0.12), screen(hex), tile_size * 0.8, 0.5, colour)?;
+                },
+                Mark::Arrow(from, to) => {
+                    let (start, end) = (screen(from), screen(to));
+                    let length = distance2(start, end).sqrt();
+                    let direction = [(end[0] - start[0]) / length, (end[1] - start[1]) / length];
+                    let across = [-direction[1], direction[0]];
+                    // the head ends short of the centre so the piece shows
+                    let tip = [end[0] - direction[0] * tile_size * 0.3, end[1] - direction[1] * tile_size * 0.3];
+                    let head = tile_size * 0.5;
+                    let base = [tip[0] - direction[0] * head, tip[1] - direction[1] * head];
+                    builder.line(&[start, base], tile_size * 0.18, colour)?;
+                    builder.triangles(&[
+                        tip,
+                        [base[0] + across[0] * head * 0.6, base[1] + across[1] * head * 0.6],
+                        [base[0] - across[0] * head * 0.6, base[1] - across[1] * head * 0.6]
+                    ], colour)?;
+                }
+            }
+        }
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())
+    }
+
+    // the heat map under the pieces, with the number of attackers on each
+    // hex, and rings round fortresses and pieces left undefended
+    fn draw_overlays(&self, ctx: &mut ggez::Context, layout: &[[f32; 2]]) -> ggez::GameResult<()> {
+        let theme = &self.theme;
+        let tile_size = self.tile_size();
+        let rotation = self.view.rotation(self.bottom());
+        let screen = |hex: usize| self.to_screen(layout[hex]);
+        if let Heat::Side(player) = self.heat {
+            let attacks = overlay::heat_map(&self.board, player);
+            let heat = match player {
+                board::Player::White => theme.heat.0,
+                board::Player::Black => theme.heat.1
+            };
+            let colouring = |hex: usize| graphics::Color { a: (attacks[hex] as f32 * 0.2).min(0.75), ..heat };
+            draw_hexes(ctx, self.board_centre(), layout, tile_size, 2.0, rotation, colouring)?;
+            for (hex, count) in attacks.iter().enumerate().filter(|(_, count)| **count > 0) {
+                let [x, y] = screen(hex);
+                let text = graphics::Text::new((count.to_string(), self.font, 10.0));
+                graphics::draw(ctx, &text, ([x - tile_size * 0.2, y - tile_size * 0.8], theme.text))?;
+            }
+        }
+        if !self.markers {
+            return Ok(());
+        }
+        let mut rings = vec![];
+        for (hex, armour) in overlay::fortresses(&self.board) {
+            rings.push((hex, match armour {
+                Armour::Armoured => theme.armoured,
+                Armour::Breached => theme.breached
+            }));
+        }
+        rings.extend(overlay::undefended(&self.board).into_iter().map(|hex| (hex, theme.warning)));
+        if rings.is_empty() {
+            return Ok(());
+        }
+        let mut builder = graphics::MeshBuilder::new();
+        for (hex, colour) in rings {
+            builder.circle(graphics::DrawMode::stroke(tile_size * 0.1), screen(hex), tile_size * 0.78, 0.5, colour)?;
+        }
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())
+    }
+
+    // each side's captures in a corner on its own side of the board, with
+    // the material lead of whoever is ahead
+    fn draw_trays(&mut self, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        let top = if self.view.upside_down(self.bottom()) { board::Player::White } else { board::Player::Black };
+        let balance = eval::material_balance(&self.board, &self.piece_values);
+        let tile = self.tile_size() / self.view.scale();
+        let centre = self.board_centre();
+        let right = centre[0] + BOARD_TILES[0] / 2.0 * tile;
+        let half_height = BOARD_TILES[1] / 2.0 * tile;
+        for (player, y) in [(top, centre[1] - half_height), (top.opposite(), centre[1] + half_height)] {
+            let mut x = right - TRAY_START * tile;
+            for (piece, count) in eval::captured_by(self.line.played(), player, &self.piece_values) {
+                self.draw_piece(ctx, player.opposite(), piece, [x, y], TRAY_SCALE / self.view.scale(), 1.0)?;
+                if count > 1 {
+                    let text = graphics::Text::new((count.to_string(), self.font, 10.0));
+                    graphics::draw(ctx, &text, ([x + 7.0, y], self.theme.text))?;
+                }
+                x += TRAY_SPACING * tile;
+            }
+            let lead = match player {
+                board::Player::White => balance,
+                board::Player::Black => -balance
+            };
+            if lead > 0 {
+                let text = graphics::Text::new((format!("+{}", pawns_text(lead)), self.font, 12.0));
+                graphics::draw(ctx, &text, ([x - 8.0, y - 6.0], self.theme.text))?;
+            }
+        }
+        Ok(())
+    }
+
+    // where the move list starts and the first of its rows showing, kept
+    // so the move that led to the position on the board is always in view
+    fn move_list_rows(&self) -> (f32, usize) {
+        let (top, bottom) = self.panels().move_list;
+        let visible = (((bottom - top) / MOVE_LIST_ROW) as usize).max(1);
+        let rows = self.line.end_ply().div_ceil(2);
+        let current = self.line.ply().saturating_sub(1) / 2;
+        let first = current.saturating_sub(visible / 2).min(rows.saturating_sub(visible));
+        (top, first)
+    }
+
+    // how many moves have been played at the move clicked on, if any
+    fn move_list_ply(&self, x: f32, y: f32) -> Option<usize> {
+        let (top, first) = self.move_list_rows();
+        let Panels { move_list: (_, bottom), columns, .. } = self.panels();
+        if !(top..bottom).contains(&y) || !(columns[1]..columns[3]).contains(&x) {
+            return None;
+        }
+        let column = if x < columns[2] { 0 } else { 1 };
+        let ply = (first + ((y - top) / MOVE_LIST_ROW) as usize) * 2 + column + 1;
+        Some(ply).filter(|ply| *ply <= self.line.end_ply())
+    }
+
+    fn draw_move_list(&self, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        let (top, first) = self.move_list_rows();
+        let Panels { move_list: (_, bottom), columns, .. } = self.panels();
+        if self.line.in_variation() {
+            let text = graphics::Text::new(("Variation, X: game", self.font, 10.0));
+            graphics::draw(ctx, &text, ([columns[0], top - MOVE_LIST_ROW], self.theme.text))?;
+        }
+        let line = self.line.moves();
+        for (index, pair) in line.chunks(2).enumerate().skip(first) {
+            let y = top + (index - first) as f32 * MOVE_LIST_ROW;
+            if y + MOVE_LIST_ROW > bottom {
+                break;
+            }
+            let number = graphics::Text::new((format!("{}.", index + 1), self.font, 10.0));
+            graphics::draw(ctx, &number, ([columns[0], y], self.theme.text))?;
+            for (column, (board, m)) in pair.iter().enumerate() {
+                let x = columns[column + 1];
+                // the move that led to the position on the board
+                if index * 2 + column + 1 == self.line.ply() {
+                    let rect = graphics::Rect::new(x - 2.0, y - 1.0, columns[2] - columns[1] - 2.0, MOVE_LIST_ROW);
+                    let mesh = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), rect, self.theme.last_move)?;
+                    graphics::draw(ctx, &mesh, graphics::DrawParam::default())?;
+                }
+                let text = graphics::Text::new((notation::describe_move(board, *m), self.font, 10.0));
+                graphics::draw(ctx, &text, ([x, y], self.theme.text))?;
+            }
+        }
+        Ok(())
+    }
+
+    // the bar is White's share, an even position fills it halfway
+    fn draw_analysis(&self, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        let Panels { analysis, bar, .. } = self.panels();
+        // scores are shown from White's side
+        let sign = match self.board.current_turn {
+            board::Player::White => 1,
+            board::Player::Black => -1
+        };
+        let white_share = match &self.analysis_info {
+            Some(info) => {
+                let score = sign * info.score;
+                if search::is_mate_score(score) {
+                    if score > 0 { 1.0 } else { 0.0 }
+                } else {
+                    1.0 / (1.0 + (-score as f32 / 250.0).exp())
+                }
+            },
+            None => 0.5
+        };
+        let black_part = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), bar, graphics::Color::new(0.2, 0.2, 0.2, 1.0))?;
+        graphics::draw(ctx, &black_part, graphics::DrawParam::default())?;
+        let white_height = bar.h * white_share;
+        let white_part = graphics::Rect::new(bar.x, bar.y + bar.h - white_height, bar.w, white_height);
+        let white_part = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), white_part, graphics::Color::WHITE)?;
+        graphics::draw(ctx, &white_part, graphics::DrawParam::default())?;
+
+        let text = match &self.analysis_info {
+            Some(info) => {
+                let millis = info.elapsed.as_millis().max(1) as u64;
+                let mut text = format!("Analysis (A to stop)\ndepth {}, {} kn/s\n", info.depth, info.nodes / millis);
+                for (index, line) in info.lines.iter().enumerate() {
+                    text.push_str(&format!("\n{}. {}\n{}\n", index + 1, score_text(sign * line.score), line_text(&self.board, &line.pv)));
+                }
+                text
+            },
+            None if self.analysis.is_some() => "Analysis (A to stop)\nthinking...".to_string(),
+            None => "Analysis (A to stop)".to_string()
+        };
+        // lines past the bottom are left off
+        let mut text = ggez::graphics::Text::new((text, self.font, PANEL_TEXT));
+        text.set_bounds([analysis.w, analysis.h], graphics::Align::Left);
+        ggez::graphics::draw(ctx, &text, ([analysis.x, analysis.y], self.theme.text))?;
+        Ok(())
+    }
+}
+
+// how many lines text wraps to at word breaks in width, the way the text
+// is laid out when drawn
+fn wrapped_lines(text: &str, size: f32, width: f32) -> usize {
+    let per_line = ((width / (size * CHAR_WIDTH)) as usize).max(1);
+    text.lines()
+        .map(|line| {
+            let mut lines = 1;
+            let mut used = 0;
+            for word in line.split(' ') {
+                let length = word.chars().count();
+                if used > 0 && used + 1 + length > per_line {
+                    lines += 1;
+                    used = 0;
+                }
+                used += if used > 0 { 1 + length } else { length };
+                while used > per_line {
+                    lines += 1;
+                    used -= per_line;
+                }
+            }
+            lines
+        })
+        .sum()
+}
+
+// centipawns as pawns without trailing zeros, 350 is 3.5
+fn pawns_text(centipawns: i32) -> String {
+    let text = format!("{:.2}", centipawns as f32 / 100.0);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+// in pawns, or the moves to a forced king capture
+fn score_text(score: i32) -> String {
+    if search::is_mate_score(score) {
+        let plies = search::MATE - score.abs();
+        format!("{}K in {}", if score > 0 { "+" } else { "-" }, (plies + 1) / 2)
+    } else {
+        format!("{:+.2}", score as f32 / 100.0)
+    }
+}
+
+fn line_text(board: &board::Board, pv: &[Move]) -> String {
+    let mut board = board.clone();
+    let mut moves = vec![];
+    for m in pv.iter().take(6) {
+        if !logic::is_legal(&board, *m) {
+            break;
+        }
+        moves.push(notation::describe_move(&board, *m));
+        logic::make_move(&mut board, *m);
+    }
+    moves.join(" ")
 }
 
 impl ggez::event::EventHandler for App {
@@ -45,70 +1451,82 @@ impl ggez::event::EventHandler for App {
             }
         };
         let button_down = mouse::button_pressed(ctx, mouse::MouseButton::Left);
+        let button_pressed = !self.last_button && button_down;
         let button_released = self.last_button && !button_down;
         self.last_button = button_down;
-        let pick = if let Some(pointer_pos) = pointer_pos {
-            let pick_x = (pointer_pos.x - BOARD_POS[0]) / TILE_SIZE;
-            let pick_y = (pointer_pos.y - BOARD_POS[1]) / TILE_SIZE;
-            hex_pick([pick_x, pick_y], &self.board.positions)  
-        } else {
-            None
-        };
+        let seconds = ggez::timer::delta(ctx).as_secs_f32();
+        self.animations.retain_mut(|animation| animation.advance(seconds));
+        match self.hint_search.as_ref().map(|search| search.result.try_recv()) {
+            Some(Ok(hint)) => {
+                self.hint = hint;
+                self.hint_search = None;
+            },
+            Some(Err(mpsc::TryRecvError::Disconnected)) => self.hint_search = None,
+            _ => {}
+        }
+        self.update_analysis();
+        let layout = self.layout();
+        let pick = pointer_pos.and_then(|pointer_pos| self.pick_hex(&layout, pointer_pos));
+        if !matches!(self.state, AppState::SelectedPiece(..)) {
+            self.drag = None;
+        }
         match self.state {
             AppState::GameWonBy(_player) => {
 
+            },
+            AppState::Thinking(player) => {
+                let reply = self.thinking.as_ref().map(|thinking| thinking.result.try_recv());
+                match reply {
+                    Some(Ok(Ok((agent, Some(m))))) => {
+                        self.thinking = None;
+                        self.book_move = agent.played_from_book();
+                        self.agent = Some(agent);
+                        self.play_move(player, m);
+                    },
+                    Some(Err(mpsc::TryRecvError::Empty)) => {},
+                    Some(Ok(Err(e))) => {
+                        self.message = Some(e);
+                        self.thinking = None;
+                        self.computer = None;
+                        self.state = AppState::Waiting(player);
+                    },
+                    _ => {
+                        // no legal moves, hand the board back
+                        self.thinking = None;
+                        self.computer = None;
+                        self.state = AppState::Waiting(player);
+                    }
+                }
+            },
+            AppState::Waiting(player) if self.computer == Some(player) => {
+                // not while looking back through the game
+                if !self.line.looking_back() {
+                    self.start_thinking(player);
+                }
             },
             AppState::Waiting(player) => {
+                match self.own_piece(player, pick) {
+                    Some(hex) if button_pressed && self.move_input == MoveInput::Drag => self.pick_up(player, hex, None),
+                    Some(hex) if button_released => self.state = AppState::SelectedPiece(player, hex),
+                    _ => {}
+                }
+            },
+            AppState::Confirming(_, m) => {
+                // clicking the target again plays the move, anywhere else takes it back
                 if button_released {
-                    if let Some(hex) = pick {
-                        if let Some((colour, _piece)) = self.board.hexes[hex] {
-                            if player == colour {
-                                self.state = AppState::SelectedPiece(player, hex);
-                            }
-                        }
-                    }
+                    self.confirm_move(pick == Some(m.to));
                 }
             },
             AppState::SelectedPiece(player, selected) => {
-                if button_released {
-                    if Some(selected) == pick {
-                        self.state = AppState::Waiting(player);
-                    } else {
-                        if let Some(pick) = pick {
-                            let mut valid_moves = crate::logic::find_valid_moves(&self.board, selected);
-                            valid_moves.retain(|h| crate::logic::is_vulnerable(&self.board, *h));
-                            if valid_moves.contains(&pick) {
-                                let target = self.board.hexes[pick];
-                                if Some((player, crate::pieces::Piece::Pawn)) == self.board.hexes[selected] {
-                                    if board::is_queening_hex(pick, player) {
-                                        self.board.hexes[pick] = Some((player, crate::pieces::Piece::Queen));
-                                        self.board.hexes[selected] = None;
-                                    } else {
-                                        self.board.hexes[pick] = self.board.hexes[selected];
-                                        self.board.hexes[selected] = None;
-                                    }
-                                } else {
-                                    self.board.hexes[pick] = self.board.hexes[selected];
-                                    self.board.hexes[selected] = None;
-                                }
-                                match target {
-                                    Some((_, crate::pieces::Piece::King)) => {
-                                        self.state = AppState::GameWonBy(player)
-                                    },
-                                    _ => {
-                                        self.state = AppState::Waiting(player.opposite());
-                                        self.board.current_turn = player.opposite();
-                                        self.board.threats = crate::logic::count_threats(&self.board);
-
-                                    }
-                                }
-
-                            } else if let Some((other, _)) = self.board.hexes[pick] {
-                                if other == player {
-                                    self.state = AppState::SelectedPiece(player, pick);
-                                } 
-                            }
-                        }
+                if let Some(drag) = self.drag {
+                    if button_released {
+                        self.drop_piece(player, drag, pick);
+                    }
+                } else if let Some(hex) = self.own_piece(player, pick).filter(|_| button_pressed && self.move_input == MoveInput::Drag) {
+                    self.pick_up(player, hex, Some(selected));
+                } else if button_released {
+                    if let Some(pick) = pick {
+                        self.choose_hex(pick);
                     }
                 }
             }
@@ -118,81 +1536,114 @@ impl ggez::event::EventHandler for App {
     }
 
     fn draw(&mut self, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
-        graphics::clear(ctx, graphics::Color::BLACK);
+        let theme = self.theme;
+        graphics::clear(ctx, theme.background);
         
         
-        let piece_sz = 32.0;
         let pointer_pos = {
-            let pointer_pos = ggez::input::mouse::position(&ctx);
-            if ggez::graphics::screen_coordinates(&ctx).contains(pointer_pos) {
+            let pointer_pos = ggez::input::mouse::position(ctx);
+            if ggez::graphics::screen_coordinates(ctx).contains(pointer_pos) {
                 Some(pointer_pos)
             } else { 
                 None
             }
         };
 
-        let pick = if let Some(pointer_pos) = pointer_pos {
-            let pick_x = (pointer_pos.x - BOARD_POS[0]) / TILE_SIZE;
-            let pick_y = (pointer_pos.y - BOARD_POS[1]) / TILE_SIZE;
-            hex_pick([pick_x, pick_y], &self.board.positions)  
-        } else {
-            None
+        let layout = self.layout();
+        let pick = pointer_pos.and_then(|pointer_pos| self.pick_hex(&layout, pointer_pos));
+
+        // the hint's hexes show through wherever nothing else is highlighted
+        let hint = self.hint;
+        let board = &self.board;
+        let tile_size = self.tile_size();
+        let centre = self.board_centre();
+        let rotation = self.view.rotation(self.bottom());
+        let last_move = self.line.played().last().map(|(_, m)| *m);
+        let base = |hex| match (hint, last_move) {
+            (Some(m), _) if m.from == hex || m.to == hex => theme.hint,
+            (_, Some(m)) if m.from == hex || m.to == hex => theme.last_move,
+            _ => theme.tile(board, hex)
         };
 
         // draw board
         match self.state {
             AppState::GameWonBy(_player) => {
-                let colouring = |_hex|  ggez::graphics::Color::new(0.5, 0.25, 0.0, 1.0);
-                draw_hexes(ctx, BOARD_POS, &self.board.positions, TILE_SIZE, 2.0, colouring)?;
+                let colouring = |hex| theme.tile(board, hex);
+                draw_hexes(ctx, centre, &layout, tile_size, 2.0, rotation, colouring)?;
 
             },
-            AppState::Waiting(_   ) => {
+            AppState::Waiting(_) | AppState::Thinking(_) => {
                 let colouring = |hex| match pick {
-                    Some(pick) if hex == pick =>  ggez::graphics::Color::new(1.0, 0.5, 0.0, 1.0),
-                    _ => ggez::graphics::Color::new(0.5, 0.25, 0.0, 1.0)
+                    Some(pick) if hex == pick => theme.pointer,
+                    _ => base(hex)
                 };
-                draw_hexes(ctx, BOARD_POS, &self.board.positions, TILE_SIZE, 2.0, colouring)?;
+                draw_hexes(ctx, centre, &layout, tile_size, 2.0, rotation, colouring)?;
             },
             AppState::SelectedPiece(_, selected_hex) => {   
-                let mut valid_moves = crate::logic::find_valid_moves(&self.board, selected_hex);
-                valid_moves.retain(|h| crate::logic::is_vulnerable(&self.board, *h));
+                let valid_moves = self.targets(selected_hex);
                 let colouring = |hex|
                     if selected_hex == hex { 
-                        ggez::graphics::Color::new(1.0, 0.0,0.0, 1.0) 
+                        theme.selected
                     } else if let Some(pick) = pick {
                         if hex == pick && valid_moves.contains(&hex) {
-                            ggez::graphics::Color::new(0.0, 1.0, 0.0, 1.0)
+                            theme.target
                         } else if hex == pick {
-                             ggez::graphics::Color::new(1.0, 0.5, 0.0, 1.0)
+                            theme.pointer
                         } else if valid_moves.contains(&hex) {
-                            ggez::graphics::Color::new(0.0, 0.5, 0.0, 1.0)
+                            theme.moves
                         } else {
-                            ggez::graphics::Color::new(0.5, 0.25, 0.0, 1.0)
+                            base(hex)
                         }
                     } else {
-                        ggez::graphics::Color::new(0.5, 0.25, 0.0, 1.0)
+                        base(hex)
                     };
-                draw_hexes(ctx, BOARD_POS, &self.board.positions, TILE_SIZE, 2.0, colouring)?;
+                draw_hexes(ctx, centre, &layout, tile_size, 2.0, rotation, colouring)?;
+            },
+            AppState::Confirming(_, m) => {
+                let endangered = self.warning.as_ref().map_or(vec![], |warning| warning.hexes.clone());
+                let colouring = |hex|
+                    if hex == m.from {
+                        theme.selected
+                    } else if hex == m.to {
+                        theme.target
+                    } else if endangered.contains(&hex) {
+                        theme.warning
+                    } else {
+                        base(hex)
+                    };
+                draw_hexes(ctx, centre, &layout, tile_size, 2.0, rotation, colouring)?;
             }
         }
 
-        // draw pieces
-        for hex in 0..board::HEX_COUNT {
+        self.draw_overlays(ctx, &layout)?;
+        if let Some(cursor) = self.cursor {
+            let outline = graphics::Mesh::new_polygon(ctx, graphics::DrawMode::stroke(0.12), &crate::geometry::HEX_VERTICES, theme.pointer)?;
+            let pos = layout[cursor];
+            let params = graphics::DrawParam::default()
+                .dest(self.to_screen(pos))
+                .scale([tile_size - 2.0, tile_size - 2.0])
+                .rotation(rotation);
+            graphics::draw(ctx, &outline, params)?;
+        }
+
+        self.draw_labels(ctx)?;
+
+        // draw pieces, moving ones and then a dragged one on top
+        let dragged = self.drag.map(|drag| drag.from);
+        for (hex, pos) in layout.iter().enumerate() {
             if let Some((player, piece)) = self.board.hexes[hex] {
-                let pos = self.board.positions[hex];
-                let dest: Point2<f32> = [BOARD_POS[0] - piece_sz / 2.5 + pos[0] * TILE_SIZE, BOARD_POS[1] - piece_sz / 2.5 + pos[1] * TILE_SIZE].into();
-                let symbol = ggez::graphics::Text::new((piece.code(), self.font, piece_sz));
-                let color = match player {
-                    board::Player::White => ggez::graphics::Color::WHITE,
-                    board::Player::Black => ggez::graphics::Color::BLACK
-                };
-                let params = ggez::graphics::DrawParam {
-                    color,
-                    ..Default::default()
-                }.dest(dest);
-                ggez::graphics::draw(ctx, &symbol, params)?;
+                if dragged != Some(hex) && !self.animations.iter().any(|animation| animation.to == hex) {
+                    let dest = self.to_screen(*pos);
+                    self.draw_piece(ctx, player, piece, dest, 1.0, 1.0)?;
+                }
             }
         }
+        self.draw_animations(ctx, &layout)?;
+        self.draw_annotations(ctx, &layout)?;
+        self.draw_trays(ctx)?;
+        if let (Some((player, piece)), Some(pointer_pos)) = (dragged.and_then(|hex| self.board.hexes[hex]), pointer_pos) {
+            self.draw_piece(ctx, player, piece, [pointer_pos.x, pointer_pos.y], 1.0, 1.0)?;
+        }
 
         let turn_msg = 
             ggez::graphics::Text::new((
@@ -200,17 +1651,187 @@ impl ggez::event::EventHandler for App {
                     AppState::GameWonBy(player) => {
                         format!("{:?} wins", player)        
                     },
+                    AppState::Thinking(player) => {
+                        format!("{:?} thinking...", player)
+                    },
                     _ => {
                         format!("{:?} turn", self.board.current_turn) 
                     }
                 }
                  , self.font, 24.0));
-        ggez::graphics::draw(ctx, &turn_msg, ([200.0, 8.0],))?;        
-        let state_msg = ggez::graphics::Text::new((format!("{:?}", self.state), self.font, 16.0));
-        ggez::graphics::draw(ctx, &state_msg, ([200.0, 560.0],))?;
+        ggez::graphics::draw(ctx, &turn_msg, ([PANEL_WIDTH + 20.0, 8.0], theme.text))?;        
+        let computer_msg = match (&self.puzzle, self.computer) {
+            (Some(progress), _) => {
+                let puzzle = &self.puzzles[progress.index];
+                let verdict = if progress.solved { "solved, P for the next one".to_string() } else { puzzle.themes.join(", ") };
+                format!("Puzzle {}/{}: {}", progress.index + 1, self.puzzles.len(), verdict)
+            },
+            (None, Some(player)) => format!("{:?} plays {:?} (C to change, M to switch)", self.computer_kind, player),
+            (None, None) => "Press C to play against the computer, P for puzzles".to_string()
+        };
+        let computer_msg = match (&self.message, &self.warning) {
+            (_, Some(warning)) => format!("{} Y to play it, N to choose again", warning.text),
+            (Some(message), None) => message.clone(),
+            (None, None) if self.book_move => format!("{}, book move", computer_msg),
+            (None, None) => computer_msg
+        };
+        let computer_msg = ggez::graphics::Text::new((computer_msg, self.font, 16.0));
+        ggez::graphics::draw(ctx, &computer_msg, ([PANEL_WIDTH + 20.0, 36.0], theme.text))?;
+        let panels = self.panels();
+        let panel_bounds = [PANEL_WIDTH - 2.0 * PANEL_MARGIN, f32::INFINITY];
+        if let Some(verdict) = self.verdict_text() {
+            let mut verdict = ggez::graphics::Text::new((verdict, self.font, PANEL_TEXT));
+            verdict.set_bounds(panel_bounds, graphics::Align::Left);
+            ggez::graphics::draw(ctx, &verdict, (panels.verdict, theme.text))?;
+        }
+        let mut assist_msg = ggez::graphics::Text::new((self.options_text(), self.font, PANEL_TEXT));
+        assist_msg.set_bounds(panel_bounds, graphics::Align::Left);
+        ggez::graphics::draw(ctx, &assist_msg, (panels.options, theme.text))?;
+        if let Some(typed) = &self.typed {
+            let typed = ggez::graphics::Text::new((format!("Move: {}_", typed), self.font, PANEL_TEXT));
+            ggez::graphics::draw(ctx, &typed, ([PANEL_MARGIN, self.window[1] - MOVE_LIST_BOTTOM + PANEL_GAP], theme.text))?;
+        }
+        if self.analysing {
+            self.draw_analysis(ctx)?;
+        }
+        self.draw_move_list(ctx)?;
         graphics::present(ctx)?;
         Ok(())
     }
+
+    fn key_down_event(&mut self, ctx: &mut ggez::Context, keycode: KeyCode, keymods: KeyMods, _repeat: bool) {
+        if self.typed.is_some() {
+            self.type_key(keycode);
+            return;
+        }
+        if let Some(direction) = cursor_direction(keycode, keymods) {
+            self.move_cursor(direction);
+            return;
+        }
+        if keymods.contains(KeyMods::CTRL) {
+            match keycode {
+                KeyCode::S => self.save_game(),
+                KeyCode::Z => self.undo(),
+                KeyCode::N => self.new_game(),
+                KeyCode::R => self.resign(),
+                _ => {}
+            }
+            return;
+        }
+        match keycode {
+            KeyCode::Escape => ggez::event::quit(ctx),
+            KeyCode::C => {
+                self.message = None;
+                self.cycle_computer();
+            },
+            KeyCode::M => self.switch_computer_kind(),
+            KeyCode::P => self.next_puzzle(),
+            KeyCode::H => self.request_hint(),
+            KeyCode::A => self.toggle_analysis(),
+            KeyCode::L => self.cycle_level(),
+            KeyCode::S => self.cycle_style(),
+            KeyCode::B => self.blunder_check = !self.blunder_check,
+            KeyCode::T => self.cycle_theme(),
+            KeyCode::G => self.cycle_piece_set(),
+            KeyCode::F => self.view.flipped = !self.view.flipped,
+            KeyCode::V => self.view.auto_flip = !self.view.auto_flip,
+            KeyCode::O => self.toggle_rotation(),
+            KeyCode::D => self.toggle_move_input(),
+            KeyCode::I => self.heat = self.heat.next(),
+            KeyCode::K => self.markers = !self.markers,
+            KeyCode::Q => {
+                self.instant = !self.instant;
+                self.animations.clear();
+            },
+            KeyCode::U | KeyCode::Back => self.undo(),
+            KeyCode::Tab => self.typed = Some(String::new()),
+            KeyCode::Left => self.go_to(self.line.ply().saturating_sub(1)),
+            KeyCode::Right => self.go_to(self.line.ply() + 1),
+            KeyCode::Up | KeyCode::Home => self.go_to(0),
+            KeyCode::Down | KeyCode::End => self.go_to(self.line.end_ply()),
+            KeyCode::X => self.back_to_game(),
+            KeyCode::Return | KeyCode::NumpadEnter => self.enter(),
+            KeyCode::Y => self.confirm_move(true),
+            KeyCode::N => self.confirm_move(false),
+            _ => {}
+        }
+    }
+
+    // drawing in pixels at any size, with the board and pieces grown or
+    // shrunk to fit
+    fn resize_event(&mut self, ctx: &mut ggez::Context, width: f32, height: f32) {
+        self.window = [width, height];
+        self.glyphs.clear();
+        let _ = graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, width, height));
+    }
+
+    // the right button draws circles, or arrows when dragged
+    fn mouse_button_down_event(&mut self, ctx: &mut ggez::Context, button: mouse::MouseButton, x: f32, y: f32) {
+        if button == mouse::MouseButton::Right {
+            let colour = annotation_colour(ggez::input::keyboard::active_mods(ctx));
+            self.annotating = self.pick_hex(&self.layout(), [x, y].into()).map(|hex| (hex, colour));
+        }
+    }
+
+    fn mouse_button_up_event(&mut self, _ctx: &mut ggez::Context, button: mouse::MouseButton, x: f32, y: f32) {
+        if button == mouse::MouseButton::Left {
+            if let Some(ply) = self.move_list_ply(x, y) {
+                self.go_to(ply);
+            }
+        }
+        if button != mouse::MouseButton::Right {
+            return;
+        }
+        if let (Some((from, colour)), Some(to)) = (self.annotating.take(), self.pick_hex(&self.layout(), [x, y].into())) {
+            let mark = if from == to { Mark::Circle(from) } else { Mark::Arrow(from, to) };
+            let ply = self.line.ply();
+            annotation::toggle(self.line.annotations.entry(ply).or_default(), Annotation { mark, colour });
+        }
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut ggez::Context, character: char) {
+        if let Some(typed) = self.typed.as_mut() {
+            if character.is_ascii_alphanumeric() || matches!(character, '-' | '=') {
+                typed.push(character);
+            }
+        }
+    }
+}
+
+// green, or red with shift, blue with ctrl and yellow with alt
+fn annotation_colour(keymods: KeyMods) -> annotation::Colour {
+    if keymods.contains(KeyMods::SHIFT) {
+        annotation::Colour::Red
+    } else if keymods.contains(KeyMods::CTRL) {
+        annotation::Colour::Blue
+    } else if keymods.contains(KeyMods::ALT) {
+        annotation::Colour::Yellow
+    } else {
+        annotation::Colour::Green
+    }
+}
+
+// the numeric keypad, or shift with the letters around S, as a direction
+// on screen in degrees clockwise from right
+fn cursor_direction(keycode: KeyCode, keymods: KeyMods) -> Option<f32> {
+    let shifted = keymods.contains(KeyMods::SHIFT);
+    match keycode {
+        KeyCode::Numpad6 => Some(0.0),
+        KeyCode::Numpad3 => Some(45.0),
+        KeyCode::Numpad2 => Some(90.0),
+        KeyCode::Numpad1 => Some(135.0),
+        KeyCode::Numpad4 => Some(180.0),
+        KeyCode::Numpad7 => Some(225.0),
+        KeyCode::Numpad8 => Some(270.0),
+        KeyCode::Numpad9 => Some(315.0),
+        KeyCode::D if shifted => Some(0.0),
+        KeyCode::X if shifted => Some(45.0),
+        KeyCode::Z if shifted => Some(135.0),
+        KeyCode::A if shifted => Some(180.0),
+        KeyCode::W if shifted => Some(225.0),
+        KeyCode::E if shifted => Some(315.0),
+        _ => None
+    }
 }
 
 fn distance2(a: [f32; 2], b: [f32; 2]) -> f32 {
@@ -232,7 +1853,7 @@ pub fn hex_pick(pick: [f32;2], hex_positions: &[[f32;2]]) -> Option<usize> {
     result
 }
 
-pub fn draw_hexes<F>(ctx: &mut ggez::Context, dest: [f32; 2], hex_positions: &[[f32;2]], tile_sz: f32, border: f32, colouring: F) -> ggez::GameResult<()>
+pub fn draw_hexes<F>(ctx: &mut ggez::Context, dest: [f32; 2], hex_positions: &[[f32;2]], tile_sz: f32, border: f32, rotation: f32, colouring: F) -> ggez::GameResult<()>
 where F: Fn(usize) -> ggez::graphics::Color {
     let hex_mesh = ggez::graphics::Mesh::new_polygon(
         ctx,
@@ -248,7 +1869,7 @@ where F: Fn(usize) -> ggez::graphics::Color {
             trans: Transform::Values {
                 dest: target.into(),
                 scale: [tile_sz - border, tile_sz - border].into(),
-                rotation: 0.0,
+                rotation,
                 offset: [0.0, 0.0].into() 
             },
             ..Default::default()
@@ -256,4 +1877,4 @@ where F: Fn(usize) -> ggez::graphics::Color {
         graphics::draw(ctx,&hex_mesh, params)?;
     }
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,6 @@
+// The search engine on its own, speaking the text protocol on stdin and
+// stdout so other programs can play against it.
+fn main() {
+    let stdin = std::io::stdin();
+    hexchess::protocol::run_engine(stdin.lock());
+}
@@ -41,7 +41,7 @@ pub fn is_queening_hex(hex: usize, player: Player) -> bool {
         }
     }
 }
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum Player {
     White,
     Black
@@ -66,6 +66,7 @@ pub enum Direction {
 
 pub type Hexes = [Option<(Player, Piece)>; HEX_COUNT];
 
+#[derive(Clone)]
 pub struct Board {
     pub current_turn: Player,
     pub hexes: [Option<(Player, Piece)>; HEX_COUNT],
@@ -76,6 +77,12 @@ pub struct Board {
     //files: [usize; HEX_COUNT]
 }
 
+impl Default for Board {
+    fn default() -> Board {
+        Board::new()
+    }
+}
+
 impl Board {
     pub fn new() -> Board {
         let mut b = Board {
@@ -88,9 +95,33 @@ impl Board {
         b.threats = threats;
         b
     }
+
+    pub fn from_hexes(hexes: Hexes, current_turn: Player) -> Board {
+        let mut b = Board {
+            current_turn,
+            hexes,
+            positions: crate::geometry::create_board_positions(),
+            threats: [0; HEX_COUNT]
+        };
+        b.threats = crate::logic::count_threats(&b);
+        b
+    }
+
+    pub fn hash(&self) -> u64 {
+        crate::zobrist::hash(&self.hexes, self.current_turn)
+    }
+
+    // reflected left to right, the side to move is unchanged
+    pub fn mirrored(&self) -> Board {
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        for hex in 0..HEX_COUNT {
+            hexes[mirror_hex(hex)] = self.hexes[hex];
+        }
+        Board::from_hexes(hexes, self.current_turn)
+    }
 }
 
-fn get_rank(hex: usize) -> usize {
+pub fn get_rank(hex: usize) -> usize {
     let mut cursor = hex;
     for (rank, len) in RANK_LENGTHS.iter().enumerate() {
         if cursor < *len {
@@ -102,6 +133,20 @@ fn get_rank(hex: usize) -> usize {
     panic!("invalid board index: {}", hex);
 }
 
+// the hex in the same rank at the same distance from the other end
+pub fn mirror_hex(hex: usize) -> usize {
+    let file = get_file(hex);
+    hex - file + RANK_LENGTHS[get_rank(hex)] - 1 - file
+}
+
+// steps between two hexes, using doubled-width coordinates
+pub fn hex_distance(a: usize, b: usize) -> usize {
+    let column = |hex: usize| OFFS[get_rank(hex)] as i32 + 2 * get_file(hex) as i32;
+    let d_rank = (get_rank(a) as i32 - get_rank(b) as i32).abs();
+    let d_column = (column(a) - column(b)).abs();
+    (d_rank + ((d_column - d_rank) / 2).max(0)) as usize
+}
+
 fn validate_hex(hex: usize) {
     assert!(hex < HEX_COUNT, "invalid board index: {}", hex);
 }
@@ -129,7 +174,7 @@ fn on_bottom_rank(hex: usize) -> bool {
 
 
 
-fn get_file(hex: usize) -> usize {
+pub fn get_file(hex: usize) -> usize {
     let mut cursor = hex;
     for len in RANK_LENGTHS.iter() {
         if cursor < *len {
@@ -233,6 +278,7 @@ pub fn down_right(hex: usize) -> Option<usize> {
     }
 }
 
+#[allow(clippy::match_like_matches_macro)]
 pub fn has_white_piece(hexes: Hexes, hex: usize) -> bool {
     match hexes[hex] {
         Some((Player::White, _)) => true,
@@ -240,6 +286,7 @@ pub fn has_white_piece(hexes: Hexes, hex: usize) -> bool {
     }
 }
 
+#[allow(clippy::match_like_matches_macro)]
 pub fn has_black_piece(hexes: Hexes, hex: usize) -> bool {
     match hexes[hex] {
         Some((Player::Black, _)) => true,
@@ -247,6 +294,7 @@ pub fn has_black_piece(hexes: Hexes, hex: usize) -> bool {
     }
 }
 
+#[allow(clippy::match_like_matches_macro)]
 pub fn has_colored_piece(hexes: Hexes, hex: usize, color: Player) -> bool {
     match hexes[hex] {
         Some((c, _)) if c == color => true,
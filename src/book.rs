@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use crate::agent::Agent;
+use crate::board::{self, Board};
+use crate::eval::EvalWeights;
+use crate::logic::{self, Move, Outcome};
+use crate::match_runner;
+use crate::record::{self, GameRecord};
+use crate::rng::Rng;
+
+// Book files are a header followed by entries sorted by key:
+//
+//   "HXBK", version byte, entry count as u32
+//   key u64, from u8, to u8, weight u16      repeated, little endian
+//
+// A position and its left-right mirror share one key, the smaller of
+// their two hashes, and moves are stored as seen from the position that
+// hash belongs to.
+
+const MAGIC: &[u8; 4] = b"HXBK";
+const VERSION: u8 = 1;
+const ENTRY_BYTES: usize = 12;
+pub const DEFAULT_BOOK_PLIES: usize = 20;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct BookEntry {
+    key: u64,
+    m: Move,
+    weight: u16
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Book {
+    entries: Vec<BookEntry>
+}
+
+fn mirror_move(m: Move) -> Move {
+    Move {
+        from: board::mirror_hex(m.from),
+        to: board::mirror_hex(m.to)
+    }
+}
+
+// the book key and whether moves have to be mirrored to match it
+fn canonical_key(board: &Board) -> (u64, bool) {
+    let key = board.hash();
+    let mirrored = board.mirrored().hash();
+    if mirrored < key {
+        (mirrored, true)
+    } else {
+        (key, false)
+    }
+}
+
+impl Book {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn positions(&self) -> usize {
+        let mut keys: Vec<u64> = self.entries.iter().map(|e| e.key).collect();
+        keys.dedup();
+        keys.len()
+    }
+
+    // legal book moves for the position with their weights
+    pub fn probe(&self, board: &Board) -> Vec<(Move, u16)> {
+        let (key, mirrored) = canonical_key(board);
+        let start = self.entries.partition_point(|e| e.key < key);
+        self.entries[start..].iter()
+            .take_while(|e| e.key == key)
+            .map(|e| (if mirrored { mirror_move(e.m) } else { e.m }, e.weight))
+            .filter(|(m, _)| logic::is_legal(board, *m))
+            .collect()
+    }
+
+    pub fn choose(&self, board: &Board, rng: &mut Rng) -> Option<Move> {
+        let moves = self.probe(board);
+        let total: usize = moves.iter().map(|(_, weight)| *weight as usize).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = rng.below(total);
+        for (m, weight) in moves {
+            if pick < weight as usize {
+                return Some(m);
+            }
+            pick -= weight as usize;
+        }
+        None
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(9 + self.entries.len() * ENTRY_BYTES);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for entry in &self.entries {
+            bytes.extend_from_slice(&entry.key.to_le_bytes());
+            bytes.push(entry.m.from as u8);
+            bytes.push(entry.m.to as u8);
+            bytes.extend_from_slice(&entry.weight.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book, String> {
+        if bytes.len() < 9 || &bytes[..4] != MAGIC {
+            return Err("not a book file".to_string());
+        }
+        if bytes[4] != VERSION {
+            return Err(format!("unsupported book version {}", bytes[4]));
+        }
+        let count = u32::from_le_bytes(bytes[5..9].try_into().unwrap()) as usize;
+        let body = &bytes[9..];
+        if body.len() != count * ENTRY_BYTES {
+            return Err("book file is truncated".to_string());
+        }
+        let mut entries = Vec::with_capacity(count);
+        for chunk in body.chunks(ENTRY_BYTES) {
+            let m = Move { from: chunk[8] as usize, to: chunk[9] as usize };
+            if m.from >= board::HEX_COUNT || m.to >= board::HEX_COUNT {
+                return Err("book file has a move off the board".to_string());
+            }
+            entries.push(BookEntry {
+                key: u64::from_le_bytes(chunk[..8].try_into().unwrap()),
+                m,
+                weight: u16::from_le_bytes([chunk[10], chunk[11]])
+            });
+        }
+        if entries.windows(2).any(|pair| pair[0].key > pair[1].key) {
+            return Err("book file is not sorted".to_string());
+        }
+        Ok(Book { entries })
+    }
+
+    pub fn load(path: &str) -> Result<Book, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        Book::from_bytes(&bytes).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes()).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct MoveStats {
+    games: u32,
+    // two for a win, one for a draw, from the mover's side
+    points: u32
+}
+
+pub struct BookBuilder {
+    pub max_plies: usize,
+    pub min_games: u32,
+    stats: HashMap<(u64, Move), MoveStats>
+}
+
+impl BookBuilder {
+    pub fn new(max_plies: usize, min_games: u32) -> BookBuilder {
+        BookBuilder {
+            max_plies,
+            min_games,
+            stats: HashMap::new()
+        }
+    }
+
+    pub fn add_game(&mut self, game: &GameRecord) -> Result<(), String> {
+        let boards = game.boards()?;
+        let random = random_plies(game)?;
+        for (board, m) in boards.iter().zip(game.moves.iter()).take(self.max_plies).skip(random) {
+            let (key, mirrored) = canonical_key(board);
+            let m = if mirrored { mirror_move(*m) } else { *m };
+            let stats = self.stats.entry((key, m)).or_default();
+            stats.games += 1;
+            stats.points += match game.result {
+                Some(Outcome::Won(player)) if player == board.current_turn => 2,
+                Some(Outcome::Won(_)) => 0,
+                _ => 1
+            };
+        }
+        Ok(())
+    }
+
+    // moves seen too rarely or that never scored are left out
+    pub fn build(&self) -> Book {
+        let mut entries: Vec<BookEntry> = self.stats.iter()
+            .filter(|(_, stats)| stats.games >= self.min_games && stats.points > 0)
+            .map(|((key, m), stats)| BookEntry {
+                key: *key,
+                m: *m,
+                weight: stats.points.min(u16::MAX as u32) as u16
+            })
+            .collect();
+        entries.sort_by_key(|e| (e.key, e.m.from, e.m.to));
+        Book { entries }
+    }
+}
+
+// Plays from the book while it has an answer, then hands over to
+// another player. Book moves are picked at random by weight.
+pub struct BookPlayer {
+    book: Arc<Book>,
+    fallback: Box<dyn Agent>,
+    rng: Rng,
+    from_book: bool
+}
+
+impl BookPlayer {
+    pub fn new(book: Arc<Book>, fallback: Box<dyn Agent>) -> BookPlayer {
+        BookPlayer {
+            book,
+            fallback,
+            rng: Rng::from_time(),
+            from_book: false
+        }
+    }
+}
+
+impl Agent for BookPlayer {
+    fn name(&self) -> String {
+        format!("{} with book", self.fallback.name())
+    }
+
+    fn choose_move(&mut self, board: &Board, stop: &AtomicBool) -> Result<Option<Move>, String> {
+        if let Some(m) = self.book.choose(board, &mut self.rng) {
+            self.from_book = true;
+            return Ok(Some(m));
+        }
+        self.from_book = false;
+        self.fallback.choose_move(board, stop)
+    }
+
+    fn new_game(&mut self) {
+        self.from_book = false;
+        self.fallback.new_game();
+    }
+
+    fn played_from_book(&self) -> bool {
+        self.from_book
+    }
+}
+
+// how many moves at the start of a game were picked at random rather than
+// played, none unless the record says so
+fn random_plies(game: &GameRecord) -> Result<usize, String> {
+    match game.tag(match_runner::RANDOM_PLIES_TAG) {
+        Some(value) => value.parse().map_err(|_| format!("bad {} tag \"{}\"", match_runner::RANDOM_PLIES_TAG, value)),
+        None => Ok(0)
+    }
+}
+
+pub const USAGE: &str = "usage: hexchess book --output FILE [--records FILE]... [--self-play N] \
+[--player \"<player>\"] [--random-plies N] [--plies N] [--min-games N] [--seed N] [--save-games FILE]";
+
+pub fn main(args: &[String]) -> Result<(), String> {
+    let mut output = None;
+    let mut record_files = vec![];
+    let mut self_play = 0;
+    let mut player = "alphabeta depth=3".to_string();
+    let mut random_plies = 4;
+    let mut plies = DEFAULT_BOOK_PLIES;
+    let mut min_games = 1;
+    let mut seed = None;
+    let mut save_games = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or_else(|| format!("{} needs a value", arg));
+        let number = |value: String| value.parse::<u64>().map_err(|_| format!("{} needs a number", arg));
+        match arg.as_str() {
+            "--output" => output = Some(value()?),
+            "--records" => record_files.push(value()?),
+            "--self-play" => self_play = number(value()?)?,
+            "--player" => player = value()?,
+            "--random-plies" => random_plies = number(value()?)? as usize,
+            "--plies" => plies = number(value()?)? as usize,
+            "--min-games" => min_games = number(value()?)? as u32,
+            "--seed" => seed = Some(number(value()?)?),
+            "--save-games" => save_games = Some(value()?),
+            _ => return Err(USAGE.to_string())
+        }
+    }
+    let output = output.ok_or(USAGE)?;
+    let mut builder = BookBuilder::new(plies, min_games);
+    for path in &record_files {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let games = record::parse_records(&text).map_err(|e| format!("{}: {}", path, e))?;
+        for game in &games {
+            builder.add_game(game).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        println!("{}: {} games", path, games.len());
+    }
+    if self_play > 0 {
+        let weights = EvalWeights::load()?;
+        let mut rng = seed.map_or_else(Rng::from_time, Rng::new);
+        let mut games = vec![];
+        for number in 0..self_play {
+            let game = match_runner::self_play_game(&player, &weights, random_plies, &mut rng)?;
+            builder.add_game(&game)?;
+            println!("self-play game {}: {} after {} plies", number + 1, record::result_text(game.result), game.moves.len());
+            games.push(game);
+        }
+        if let Some(path) = save_games {
+            std::fs::write(&path, record::write_records(&games)).map_err(|e| format!("{}: {}", path, e))?;
+        }
+    }
+    let book = builder.build();
+    book.save(&output)?;
+    println!("{}: {} moves in {} positions", output, book.len(), book.positions());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a game whose first move leaves a position that is not its own mirror
+    fn lopsided_game() -> GameRecord {
+        let mut board = Board::new();
+        let first = logic::legal_moves(&board).into_iter()
+            .find(|m| mirror_move(*m) != *m)
+            .unwrap();
+        logic::make_move(&mut board, first);
+        let second = logic::legal_moves(&board)[0];
+        GameRecord {
+            moves: vec![first, second],
+            result: Some(Outcome::Draw),
+            ..Default::default()
+        }
+    }
+
+    fn built(game: &GameRecord) -> Book {
+        let mut builder = BookBuilder::new(DEFAULT_BOOK_PLIES, 1);
+        builder.add_game(game).unwrap();
+        builder.build()
+    }
+
+    #[test]
+    fn books_round_trip() {
+        let book = built(&lopsided_game());
+        assert_eq!(book.len(), 2);
+        let read = Book::from_bytes(&book.to_bytes()).unwrap();
+        assert_eq!(read.entries, book.entries);
+    }
+
+    #[test]
+    fn broken_books_are_rejected() {
+        let bytes = built(&lopsided_game()).to_bytes();
+        assert_eq!(Book::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(), "book file is truncated");
+        assert_eq!(Book::from_bytes(&bytes[..8]).unwrap_err(), "not a book file");
+        assert_eq!(Book::from_bytes(b"HXBK\x09\x00\x00\x00\x00").unwrap_err(), "unsupported book version 9");
+        let m = Move { from: 0, to: 1 };
+        let unsorted = Book {
+            entries: vec![BookEntry { key: 2, m, weight: 1 }, BookEntry { key: 1, m, weight: 1 }]
+        };
+        assert_eq!(Book::from_bytes(&unsorted.to_bytes()).unwrap_err(), "book file is not sorted");
+    }
+
+    #[test]
+    fn mirrored_positions_get_mirrored_moves() {
+        let game = lopsided_game();
+        let book = built(&game);
+        let board = game.boards().unwrap()[1].clone();
+        let mirrored = board.mirrored();
+        assert_ne!(board.hash(), mirrored.hash());
+        assert_eq!(book.probe(&board), vec![(game.moves[1], 1)]);
+        assert_eq!(book.probe(&mirrored), vec![(mirror_move(game.moves[1]), 1)]);
+    }
+
+    #[test]
+    fn random_opening_moves_stay_out_of_the_book() {
+        let mut game = lopsided_game();
+        game.set_tag(match_runner::RANDOM_PLIES_TAG, "1");
+        let book = built(&game);
+        assert!(book.probe(&Board::new()).is_empty());
+        assert_eq!(book.probe(&game.boards().unwrap()[1]).len(), 1);
+        game.set_tag(match_runner::RANDOM_PLIES_TAG, "one");
+        assert!(BookBuilder::new(DEFAULT_BOOK_PLIES, 1).add_game(&game).is_err());
+    }
+}
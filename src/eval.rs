@@ -0,0 +1,367 @@
+use serde::Deserialize;
+use crate::board::*;
+use crate::logic::{self, Move};
+use crate::pieces::Piece;
+
+pub const CONFIG_FILE: &str = "eval.toml";
+pub const CONFIG_ENV: &str = "HEXCHESS_EVAL";
+const CENTRE_HEX: usize = HEX_COUNT / 2;
+
+pub fn piece_value(piece: Piece) -> i32 {
+    use Piece::*;
+    match piece {
+        King => 10000,
+        Queen => 900,
+        General => 450,
+        Fortress => 350,
+        Knight => 300,
+        Bishop => 300,
+        Pawn => 100
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Material {
+    pub king: i32,
+    pub queen: i32,
+    pub knight: i32,
+    pub bishop: i32,
+    pub fortress: i32,
+    pub general: i32,
+    pub pawn: i32
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        use Piece::*;
+        Material {
+            king: piece_value(King),
+            queen: piece_value(Queen),
+            knight: piece_value(Knight),
+            bishop: piece_value(Bishop),
+            fortress: piece_value(Fortress),
+            general: piece_value(General),
+            pawn: piece_value(Pawn)
+        }
+    }
+}
+
+impl Material {
+    // "queen = 900, knight = 300", in centipawns like the [material] table
+    // of eval.toml, pieces left out keep their usual values
+    pub fn parse(text: &str) -> Result<Material, String> {
+        toml::from_str(&text.replace(',', "\n")).map_err(|e| format!("piece values: {}", e))
+    }
+
+    pub fn value(&self, piece: Piece) -> i32 {
+        use Piece::*;
+        match piece {
+            King => self.king,
+            Queen => self.queen,
+            Knight => self.knight,
+            Bishop => self.bishop,
+            Fortress => self.fortress,
+            General => self.general,
+            Pawn => self.pawn
+        }
+    }
+}
+
+// the kinds of piece player took in moves, each with the board before it,
+// and how many of each, most valuable first
+pub fn captured_by(moves: &[(Board, Move)], player: Player, values: &Material) -> Vec<(Piece, usize)> {
+    let mut captured: Vec<(Piece, usize)> = crate::pieces::ALL_PIECES.iter()
+        .map(|kind| {
+            let count = moves.iter()
+                .filter(|(board, m)| board.hexes[m.to] == Some((player.opposite(), *kind)))
+                .count();
+            (*kind, count)
+        })
+        .filter(|(_, count)| *count > 0)
+        .collect();
+    captured.sort_by_key(|(piece, _)| -values.value(*piece));
+    captured
+}
+
+// White's material less Black's, kings left out
+pub fn material_balance(board: &Board, values: &Material) -> i32 {
+    board.hexes.iter().flatten()
+        .filter(|(_, piece)| *piece != Piece::King)
+        .map(|(player, piece)| match player {
+            Player::White => values.value(*piece),
+            Player::Black => -values.value(*piece)
+        })
+        .sum()
+}
+
+// piece-hex tables from white's point of view, indexed like Board::hexes
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tables {
+    pub king: Vec<i32>,
+    pub queen: Vec<i32>,
+    pub knight: Vec<i32>,
+    pub bishop: Vec<i32>,
+    pub fortress: Vec<i32>,
+    pub general: Vec<i32>,
+    pub pawn: Vec<i32>
+}
+
+fn centrality_table(weight: i32) -> Vec<i32> {
+    (0..HEX_COUNT)
+        .map(|hex| weight * (5 - hex_distance(hex, CENTRE_HEX) as i32))
+        .collect()
+}
+
+impl Default for Tables {
+    fn default() -> Tables {
+        Tables {
+            // the king prefers to stay away from the middle
+            king: centrality_table(-4),
+            queen: centrality_table(2),
+            knight: centrality_table(6),
+            bishop: centrality_table(4),
+            fortress: centrality_table(3),
+            general: centrality_table(4),
+            pawn: vec![0; HEX_COUNT]
+        }
+    }
+}
+
+impl Tables {
+    pub fn table(&self, piece: Piece) -> &[i32] {
+        use Piece::*;
+        match piece {
+            King => &self.king,
+            Queen => &self.queen,
+            Knight => &self.knight,
+            Bishop => &self.bishop,
+            Fortress => &self.fortress,
+            General => &self.general,
+            Pawn => &self.pawn
+        }
+    }
+
+    pub fn value(&self, piece: Piece, player: Player, hex: usize) -> i32 {
+        // the board is symmetric under a half turn, which swaps the players
+        let hex = match player {
+            Player::White => hex,
+            Player::Black => HEX_COUNT - 1 - hex
+        };
+        self.table(piece)[hex]
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvalWeights {
+    pub material: Material,
+    pub tables: Tables,
+    // per pseudo-legal move
+    pub mobility: i32,
+    // per rank a pawn has advanced
+    pub pawn_advance: i32,
+    // for a pawn that can queen next move
+    pub pawn_near_queening: i32,
+    // per enemy attack on the king's hex and its neighbours
+    pub king_exposure: i32,
+    // per friendly piece next to the king
+    pub king_shelter: i32,
+    // for a fortress one attacker short of losing its armour
+    pub fortress_pressure: i32,
+    // for a fortress attacked by enough pieces to be taken
+    pub fortress_breached: i32
+}
+
+impl Default for EvalWeights {
+    fn default() -> EvalWeights {
+        EvalWeights {
+            material: Material::default(),
+            tables: Tables::default(),
+            mobility: 3,
+            pawn_advance: 8,
+            pawn_near_queening: 60,
+            king_exposure: 12,
+            king_shelter: 6,
+            fortress_pressure: 15,
+            fortress_breached: 50
+        }
+    }
+}
+
+impl EvalWeights {
+    pub fn parse(text: &str) -> Result<EvalWeights, String> {
+        let weights: EvalWeights = toml::from_str(text).map_err(|e| e.to_string())?;
+        for piece in crate::pieces::ALL_PIECES.iter() {
+            let len = weights.tables.table(*piece).len();
+            if len != HEX_COUNT {
+                return Err(format!("{} table has {} entries, expected {}", piece, len, HEX_COUNT));
+            }
+        }
+        Ok(weights)
+    }
+
+    pub fn from_file(path: &std::path::Path) -> Result<EvalWeights, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        EvalWeights::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // $HEXCHESS_EVAL if set, otherwise eval.toml when it exists, otherwise the defaults
+    pub fn load() -> Result<EvalWeights, String> {
+        if let Some(path) = std::env::var_os(CONFIG_ENV) {
+            return EvalWeights::from_file(std::path::Path::new(&path));
+        }
+        let path = std::path::Path::new(CONFIG_FILE);
+        if path.exists() {
+            EvalWeights::from_file(path)
+        } else {
+            Ok(EvalWeights::default())
+        }
+    }
+}
+
+fn ranks_advanced(hex: usize, player: Player) -> i32 {
+    match player {
+        Player::White => 10 - get_rank(hex) as i32,
+        Player::Black => get_rank(hex) as i32
+    }
+}
+
+// score for one side, positive is good for player
+fn evaluate_side(board: &Board, weights: &EvalWeights, player: Player, enemy_attacks: &[u8; HEX_COUNT]) -> i32 {
+    let mut score = 0;
+    for hex in 0..HEX_COUNT {
+        let piece = match board.hexes[hex] {
+            Some((colour, piece)) if colour == player => piece,
+            _ => continue
+        };
+        score += weights.material.value(piece);
+        score += weights.tables.value(piece, player, hex);
+        let moves = logic::find_valid_moves(board, hex);
+        match piece {
+            Piece::King => {
+                let zone = adjacent(hex);
+                let attacks: i32 = zone.iter().chain(std::iter::once(&hex))
+                    .map(|h| enemy_attacks[*h] as i32)
+                    .sum();
+                let shelter = zone.iter()
+                    .filter(|h| has_colored_piece(board.hexes, **h, player))
+                    .count() as i32;
+                score -= weights.king_exposure * attacks;
+                score += weights.king_shelter * shelter;
+            },
+            Piece::Pawn => {
+                score += weights.pawn_advance * ranks_advanced(hex, player);
+                if moves.iter().any(|t| is_queening_hex(*t, player)) {
+                    score += weights.pawn_near_queening;
+                }
+            },
+            Piece::Fortress => {
+                let attackers = board.threats[hex];
+                if attackers >= logic::FORTRESS_ARMOUR {
+                    score -= weights.fortress_breached;
+                } else if attackers + 1 == logic::FORTRESS_ARMOUR {
+                    score -= weights.fortress_pressure;
+                }
+            },
+            _ => {}
+        }
+        if piece != Piece::King {
+            score += weights.mobility * moves.len() as i32;
+        }
+    }
+    score
+}
+
+// from the point of view of the side to move
+pub fn evaluate(board: &Board, weights: &EvalWeights) -> i32 {
+    let player = board.current_turn;
+    let enemy = player.opposite();
+    let enemy_attacks = logic::attack_map(board, enemy);
+    let our_attacks = logic::attack_map(board, player);
+    evaluate_side(board, weights, player, &enemy_attacks)
+        - evaluate_side(board, weights, enemy, &our_attacks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_tables_are_rejected() {
+        let e = EvalWeights::parse("[tables]\nknight = [1, 2, 3]").err().unwrap();
+        assert_eq!(e, format!("Knight table has 3 entries, expected {}", HEX_COUNT));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(EvalWeights::parse("mobilty = 3").is_err());
+        assert!(EvalWeights::parse("[material]\nqueeen = 900").is_err());
+        assert!(Material::parse("castle = 500").is_err());
+    }
+
+    #[test]
+    fn the_example_is_the_defaults() {
+        let example = EvalWeights::parse(include_str!("../eval.example.toml")).unwrap();
+        assert_eq!(format!("{:?}", example), format!("{:?}", EvalWeights::default()));
+    }
+
+    #[test]
+    fn piece_values_keep_the_rest() {
+        let material = Material::parse("queen=900, pawn=90").unwrap();
+        assert_eq!(material.value(Piece::Queen), 900);
+        assert_eq!(material.value(Piece::Pawn), 90);
+        assert_eq!(material.value(Piece::Knight), piece_value(Piece::Knight));
+    }
+
+    #[test]
+    fn captures_and_promotions_count_with_the_given_values() {
+        let values = Material::parse("queen=1000, pawn=50").unwrap();
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        hexes[87] = Some((Player::White, Piece::King));
+        hexes[30] = Some((Player::Black, Piece::King));
+        hexes[45] = Some((Player::Black, Piece::Pawn));
+        hexes[46] = Some((Player::White, Piece::Queen));
+        hexes[47] = Some((Player::Black, Piece::Knight));
+        hexes[8] = Some((Player::White, Piece::Pawn));
+        let mut board = Board::from_hexes(hexes, Player::White);
+        assert_eq!(material_balance(&board, &values), 1000 + 50 - 50 - 300);
+        let promotion = logic::legal_moves(&board).into_iter()
+            .find(|m| m.from == 8 && is_queening_hex(m.to, Player::White))
+            .unwrap();
+        let mut moves = vec![];
+        for m in [Move { from: 46, to: 45 }, Move { from: 47, to: 45 }, promotion] {
+            assert!(logic::is_legal(&board, m));
+            moves.push((board.clone(), m));
+            logic::make_move(&mut board, m);
+        }
+        assert_eq!(board.hexes[promotion.to], Some((Player::White, Piece::Queen)));
+        assert_eq!(material_balance(&board, &values), 1000 - 300);
+        assert_eq!(captured_by(&moves, Player::White, &values), vec![(Piece::Pawn, 1)]);
+        assert_eq!(captured_by(&moves, Player::Black, &values), vec![(Piece::Queen, 1)]);
+    }
+
+    // turning the board round and swapping the colours changes nothing for
+    // the side to move, and handing the move over negates the score
+    #[test]
+    fn evaluation_is_symmetric() {
+        let weights = EvalWeights::default();
+        let mut board = Board::new();
+        for _ in 0..3 {
+            let m = logic::legal_moves(&board)[0];
+            logic::make_move(&mut board, m);
+        }
+        let mut turned: Hexes = [None; HEX_COUNT];
+        for hex in 0..HEX_COUNT {
+            turned[HEX_COUNT - 1 - hex] = board.hexes[hex].map(|(player, piece)| (player.opposite(), piece));
+        }
+        let score = evaluate(&board, &weights);
+        assert_ne!(score, 0);
+        let turned = Board::from_hexes(turned, board.current_turn.opposite());
+        assert_eq!(evaluate(&turned, &weights), score);
+        let handed_over = Board::from_hexes(board.hexes, board.current_turn.opposite());
+        assert_eq!(evaluate(&handed_over, &weights), -score);
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use crate::annotation::Annotation;
+use crate::board::Board;
+use crate::logic::Move;
+
+// The moves of the game on the board and how far into them the board is.
+// Looking back through the game keeps the moves after the position shown,
+// and the position and state the line was left in, to step forward again.
+// Playing a different move from an earlier position starts a variation and
+// puts the game aside until back_to_game. S is what the GUI keeps about the
+// position at the end of the line, whose turn it is or who has won.
+pub struct GameLine<S> {
+    // each move and the board before it
+    history: Vec<(Board, Move)>,
+    // the moves after the position on the board, the next one last
+    future: Vec<(Board, Move)>,
+    // set whenever future is not empty
+    end: Option<(Board, S)>,
+    // the game, while a variation played from an earlier position is on the board
+    main: Option<MainLine<S>>,
+    // circles and arrows, by how many moves had been played
+    pub annotations: HashMap<usize, Vec<Annotation>>
+}
+
+// a game put aside for a variation, which left it after branch moves
+struct MainLine<S> {
+    branch: usize,
+    moves: Vec<(Board, Move)>,
+    end: (Board, S),
+    annotations: HashMap<usize, Vec<Annotation>>
+}
+
+// where a step through the line leaves the board
+pub enum Shown<S> {
+    // an earlier position, for its side to move
+    Earlier(Board),
+    // the end of the line, as it was left
+    End(Board, S)
+}
+
+impl<S> Default for GameLine<S> {
+    fn default() -> GameLine<S> {
+        GameLine {
+            history: vec![],
+            future: vec![],
+            end: None,
+            main: None,
+            annotations: HashMap::new()
+        }
+    }
+}
+
+impl<S: Copy> GameLine<S> {
+    // the moves played to reach the position on the board
+    pub fn played(&self) -> &[(Board, Move)] {
+        &self.history
+    }
+
+    pub fn ply(&self) -> usize {
+        self.history.len()
+    }
+
+    // the moves of the whole line, those played and any still ahead
+    pub fn moves(&self) -> Vec<&(Board, Move)> {
+        self.history.iter().chain(self.future.iter().rev()).collect()
+    }
+
+    pub fn end_ply(&self) -> usize {
+        self.history.len() + self.future.len()
+    }
+
+    pub fn looking_back(&self) -> bool {
+        !self.future.is_empty()
+    }
+
+    pub fn in_variation(&self) -> bool {
+        self.main.is_some()
+    }
+
+    pub fn next_move(&self) -> Option<Move> {
+        self.future.last().map(|(_, m)| *m)
+    }
+
+    // the position and state at the end while looking back
+    pub fn end(&self) -> Option<&(Board, S)> {
+        self.end.as_ref()
+    }
+
+    pub fn clear(&mut self) {
+        *self = GameLine::default();
+    }
+
+    // m played from board, the position shown; callers step forward
+    // instead when m is the next move
+    pub fn play(&mut self, board: &Board, m: Move) {
+        if self.looking_back() {
+            self.start_variation();
+        }
+        self.history.push((board.clone(), m));
+    }
+
+    // the last move taken back for good, with any drawings after it, and
+    // the board before it
+    pub fn take_back(&mut self) -> Option<Board> {
+        let (board, _) = self.history.pop()?;
+        let plies = self.history.len();
+        self.annotations.retain(|ply, _| *ply <= plies);
+        Some(board)
+    }
+
+    // board and state are what is shown now, kept as the end when stepping
+    // back from it
+    pub fn step_back(&mut self, board: &Board, state: S) -> Option<Board> {
+        let (before, m) = self.history.pop()?;
+        if self.future.is_empty() {
+            self.end = Some((board.clone(), state));
+        }
+        self.future.push((before.clone(), m));
+        Some(before)
+    }
+
+    pub fn step_forward(&mut self) -> Option<Shown<S>> {
+        let entry = self.future.pop()?;
+        self.history.push(entry);
+        Some(match self.future.last() {
+            Some((next, _)) => Shown::Earlier(next.clone()),
+            None => {
+                let (board, state) = self.end.take().expect("a line looked back through keeps its end");
+                Shown::End(board, state)
+            }
+        })
+    }
+
+    // a move off the game from an earlier position puts the game aside,
+    // leaving a variation it already left earlier keeps the earlier branch
+    fn start_variation(&mut self) {
+        let branch = self.history.len();
+        let mut moves = self.history.clone();
+        moves.extend(self.future.drain(..).rev());
+        let end = self.end.take();
+        match (&mut self.main, end) {
+            (Some(main), _) => main.branch = main.branch.min(branch),
+            (None, Some(end)) => {
+                self.main = Some(MainLine { branch, moves, end, annotations: self.annotations.clone() });
+            },
+            (None, None) => {}
+        }
+        self.annotations.retain(|ply, _| *ply <= branch);
+    }
+
+    // from a variation back to where it left the game
+    pub fn back_to_game(&mut self) -> Option<Shown<S>> {
+        let main = self.main.take()?;
+        self.history = main.moves[..main.branch].to_vec();
+        self.future = main.moves[main.branch..].iter().rev().cloned().collect();
+        self.annotations = main.annotations;
+        Some(match self.future.last() {
+            Some((board, _)) => {
+                let board = board.clone();
+                self.end = Some(main.end);
+                Shown::Earlier(board)
+            },
+            None => {
+                self.end = None;
+                Shown::End(main.end.0, main.end.1)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotation::{Colour, Mark};
+    use crate::logic;
+    use crate::notation;
+
+    // the state at the end of the line
+    const LIVE: &str = "live";
+
+    // plays choice'th legal move from each position, returning the
+    // position the line ends in
+    fn play(line: &mut GameLine<&'static str>, board: &Board, choices: &[usize]) -> Board {
+        let mut board = board.clone();
+        for &choice in choices {
+            let m = logic::legal_moves(&board)[choice];
+            line.play(&board, m);
+            logic::make_move(&mut board, m);
+        }
+        board
+    }
+
+    fn back(line: &mut GameLine<&'static str>, board: &Board, steps: usize) -> Board {
+        let mut board = board.clone();
+        for _ in 0..steps {
+            board = line.step_back(&board, LIVE).unwrap();
+        }
+        board
+    }
+
+    fn texts(line: &GameLine<&'static str>) -> Vec<String> {
+        line.moves().iter().map(|(_, m)| notation::move_text(*m)).collect()
+    }
+
+    fn circle(hex: usize) -> Vec<Annotation> {
+        vec![Annotation { mark: Mark::Circle(hex), colour: Colour::Green }]
+    }
+
+    #[test]
+    fn a_new_move_from_an_earlier_position_keeps_the_game() {
+        let mut line = GameLine::default();
+        let end = play(&mut line, &Board::new(), &[0, 0, 0, 0]);
+        let game = texts(&line);
+        let board = back(&mut line, &end, 2);
+        play(&mut line, &board, &[1]);
+        assert!(line.in_variation());
+        assert_eq!((line.ply(), line.end_ply()), (3, 3));
+        match line.back_to_game() {
+            Some(Shown::Earlier(shown)) => {
+                assert_eq!(notation::position_string(&shown), notation::position_string(&board));
+            },
+            _ => panic!("expected the position the variation left")
+        }
+        assert!(!line.in_variation());
+        assert_eq!((line.ply(), texts(&line)), (2, game));
+        // stepping on reaches the end the game was left at
+        line.step_forward();
+        match line.step_forward() {
+            Some(Shown::End(shown, state)) => {
+                assert_eq!(notation::position_string(&shown), notation::position_string(&end));
+                assert_eq!(state, LIVE);
+            },
+            _ => panic!("expected the end of the game")
+        }
+        assert!(line.step_forward().is_none());
+    }
+
+    #[test]
+    fn a_second_variation_keeps_the_earlier_branch() {
+        let mut line = GameLine::default();
+        let end = play(&mut line, &Board::new(), &[0, 0, 0, 0]);
+        let game = texts(&line);
+        let board = back(&mut line, &end, 2);
+        let variation = play(&mut line, &board, &[1, 0]);
+        // leaving the variation after the branch keeps it
+        let board = back(&mut line, &variation, 1);
+        play(&mut line, &board, &[2]);
+        let board = match line.back_to_game() {
+            Some(Shown::Earlier(board)) => board,
+            _ => panic!("expected the position the variation left")
+        };
+        assert_eq!((line.ply(), texts(&line)), (2, game.clone()));
+        // leaving it before the branch moves the branch back
+        let variation = play(&mut line, &board, &[1, 0]);
+        let board = back(&mut line, &variation, 3);
+        play(&mut line, &board, &[3]);
+        line.back_to_game();
+        assert_eq!((line.ply(), texts(&line)), (1, game));
+    }
+
+    #[test]
+    fn back_to_game_restores_its_annotations() {
+        let mut line = GameLine::default();
+        let end = play(&mut line, &Board::new(), &[0, 0, 0, 0]);
+        line.annotations.insert(1, circle(10));
+        line.annotations.insert(3, circle(30));
+        let board = back(&mut line, &end, 2);
+        play(&mut line, &board, &[1]);
+        assert_eq!(line.annotations.len(), 1);
+        line.annotations.insert(3, circle(60));
+        line.back_to_game();
+        assert_eq!(line.annotations.len(), 2);
+        assert_eq!(line.annotations[&1], circle(10));
+        assert_eq!(line.annotations[&3], circle(30));
+    }
+
+    #[test]
+    fn taking_back_drops_later_annotations() {
+        let mut line = GameLine::default();
+        play(&mut line, &Board::new(), &[0, 0]);
+        line.annotations.insert(2, circle(10));
+        line.annotations.insert(1, circle(20));
+        assert!(line.take_back().is_some());
+        assert_eq!(line.annotations.keys().collect::<Vec<_>>(), vec![&1]);
+    }
+}
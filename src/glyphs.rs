@@ -0,0 +1,142 @@
+use std::f32::consts::PI;
+use ggez::graphics::{self, Color, DrawMode, Mesh, MeshBuilder};
+use crate::pieces::Piece;
+
+// Piece shapes drawn with meshes rather than a font, so every piece has a
+// recognisable outline in any theme. Shapes are laid out in a square from
+// -1 to 1 with y pointing down, the same way up as the board, and sit on a
+// shared pedestal.
+
+pub enum Part {
+    // filled with the piece colour and outlined
+    Polygon(Vec<[f32; 2]>),
+    Circle([f32; 2], f32),
+    // drawn in the outline colour only
+    Line(Vec<[f32; 2]>),
+    Dot([f32; 2], f32)
+}
+
+fn polygon(points: &[[f32; 2]]) -> Part {
+    Part::Polygon(points.to_vec())
+}
+
+fn pedestal() -> Part {
+    polygon(&[[-0.6, 0.85], [0.6, 0.85], [0.5, 0.62], [-0.5, 0.62]])
+}
+
+// points alternate between the outer and inner radius, starting at the top
+fn star(centre: [f32; 2], outer: f32, inner: f32, points: usize) -> Part {
+    let corners = (0..points * 2)
+        .map(|i| {
+            let radius = if i % 2 == 0 { outer } else { inner };
+            let angle = i as f32 * PI / points as f32 - PI / 2.0;
+            [centre[0] + radius * angle.cos(), centre[1] + radius * angle.sin()]
+        })
+        .collect();
+    Part::Polygon(corners)
+}
+
+pub fn parts(piece: Piece) -> Vec<Part> {
+    let mut parts = vec![pedestal()];
+    match piece {
+        Piece::Pawn => {
+            parts.push(polygon(&[[-0.38, 0.62], [0.38, 0.62], [0.16, -0.1], [-0.16, -0.1]]));
+            parts.push(polygon(&[[-0.3, -0.02], [0.3, -0.02], [0.3, -0.14], [-0.3, -0.14]]));
+            parts.push(Part::Circle([0.0, -0.38], 0.28));
+        },
+        Piece::King => {
+            parts.push(polygon(&[[-0.45, 0.62], [0.45, 0.62], [0.58, -0.25], [-0.58, -0.25]]));
+            parts.push(polygon(&[
+                [-0.08, -0.25], [-0.08, -0.5], [-0.28, -0.5], [-0.28, -0.66], [-0.08, -0.66], [-0.08, -0.95],
+                [0.08, -0.95], [0.08, -0.66], [0.28, -0.66], [0.28, -0.5], [0.08, -0.5], [0.08, -0.25]
+            ]));
+            parts.push(Part::Line(vec![[-0.5, 0.25], [0.5, 0.25]]));
+        },
+        Piece::Queen => {
+            parts.push(polygon(&[
+                [-0.45, 0.62], [0.45, 0.62], [0.72, -0.45], [0.42, -0.05], [0.36, -0.62], [0.15, -0.08],
+                [0.0, -0.72], [-0.15, -0.08], [-0.36, -0.62], [-0.42, -0.05], [-0.72, -0.45]
+            ]));
+            for tip in [[0.72, -0.45], [0.36, -0.62], [0.0, -0.72], [-0.36, -0.62], [-0.72, -0.45]] {
+                parts.push(Part::Circle(tip, 0.1));
+            }
+            parts.push(Part::Line(vec![[-0.5, 0.3], [0.5, 0.3]]));
+        },
+        Piece::Bishop => {
+            parts.push(polygon(&[[-0.3, 0.62], [0.3, 0.62], [0.2, 0.2], [-0.2, 0.2]]));
+            parts.push(polygon(&[
+                [-0.32, 0.22], [0.32, 0.22], [0.42, -0.1], [0.32, -0.42], [0.0, -0.72], [-0.32, -0.42], [-0.42, -0.1]
+            ]));
+            parts.push(Part::Circle([0.0, -0.82], 0.11));
+            parts.push(Part::Line(vec![[0.12, -0.46], [-0.14, -0.1]]));
+        },
+        Piece::Knight => {
+            parts.push(polygon(&[
+                [-0.45, 0.62], [0.52, 0.62], [0.48, 0.05], [0.36, -0.45], [0.12, -0.72], [0.05, -0.88],
+                [-0.05, -0.62], [-0.3, -0.48], [-0.62, -0.08], [-0.52, 0.1], [-0.22, 0.0], [0.02, -0.08],
+                [-0.32, 0.32]
+            ]));
+            parts.push(Part::Dot([-0.12, -0.4], 0.07));
+            parts.push(Part::Line(vec![[0.1, -0.62], [0.3, -0.2], [0.3, 0.3]]));
+        },
+        Piece::Fortress => {
+            parts.push(polygon(&[
+                [-0.5, 0.62], [0.5, 0.62], [0.4, -0.35], [0.56, -0.35], [0.56, -0.82], [0.33, -0.82],
+                [0.33, -0.6], [0.11, -0.6], [0.11, -0.82], [-0.11, -0.82], [-0.11, -0.6], [-0.33, -0.6],
+                [-0.33, -0.82], [-0.56, -0.82], [-0.56, -0.35], [-0.4, -0.35]
+            ]));
+            parts.push(Part::Line(vec![[-0.4, -0.35], [0.4, -0.35]]));
+            parts.push(Part::Line(vec![[-0.16, 0.62], [-0.16, 0.2], [0.0, 0.05], [0.16, 0.2], [0.16, 0.62]]));
+        },
+        Piece::General => {
+            parts.push(polygon(&[[-0.42, 0.62], [0.42, 0.62], [0.22, -0.02], [-0.22, -0.02]]));
+            parts.push(star([0.0, -0.42], 0.5, 0.2, 5));
+        }
+    }
+    parts
+}
+
+// a mesh of the piece centred on the origin, size is the distance from
+// the centre to the edge of the shape's square in pixels
+pub fn piece_mesh(ctx: &mut ggez::Context, piece: Piece, size: f32, fill: Color, outline: Color) -> ggez::GameResult<Mesh> {
+    let stroke = (size / 14.0).max(1.0);
+    let scale = |points: &[[f32; 2]]| -> Vec<[f32; 2]> {
+        points.iter().map(|p| [p[0] * size, p[1] * size]).collect()
+    };
+    let mut builder = MeshBuilder::new();
+    for part in parts(piece) {
+        match part {
+            Part::Polygon(points) => {
+                let points = scale(&points);
+                builder.polygon(DrawMode::fill(), &points, fill)?;
+                builder.polygon(DrawMode::stroke(stroke), &points, outline)?;
+            },
+            Part::Circle(centre, radius) => {
+                let centre = scale(&[centre])[0];
+                builder.circle(DrawMode::fill(), centre, radius * size, 0.1, fill)?;
+                builder.circle(DrawMode::stroke(stroke), centre, radius * size, 0.1, outline)?;
+            },
+            Part::Line(points) => {
+                builder.line(&scale(&points), stroke, outline)?;
+            },
+            Part::Dot(centre, radius) => {
+                builder.circle(DrawMode::fill(), scale(&[centre])[0], radius * size, 0.1, outline)?;
+            }
+        }
+    }
+    builder.build(ctx)
+}
+
+// the piece's letter with a rim in the outline colour, centred on dest
+pub fn draw_letter(ctx: &mut ggez::Context, piece: Piece, font: graphics::Font, size: f32, dest: [f32; 2], fill: Color, outline: Color) -> ggez::GameResult<()> {
+    let text = graphics::Text::new((piece.code(), font, size));
+    let dimensions = text.dimensions(ctx);
+    let corner = [dest[0] - dimensions.w / 2.0, dest[1] - dimensions.h / 2.0];
+    for offset in [[-1.5, 0.0], [1.5, 0.0], [0.0, -1.5], [0.0, 1.5]] {
+        let params = graphics::DrawParam::default()
+            .dest([corner[0] + offset[0], corner[1] + offset[1]])
+            .color(outline);
+        graphics::draw(ctx, &text, params)?;
+    }
+    graphics::draw(ctx, &text, graphics::DrawParam::default().dest(corner).color(fill))
+}
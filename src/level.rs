@@ -0,0 +1,192 @@
+use crate::eval::EvalWeights;
+use crate::logic::Move;
+use crate::rng::Rng;
+use crate::search::{self, Limits, PvLine};
+
+// A level weakens the engine by searching less and by not always playing
+// its best move: the root moves of a few lines are chosen between by
+// soft-max over their scores, so a move a pawn worse than the best is
+// picked about exp(-100 / temperature) times as often.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Level {
+    pub name: &'static str,
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    // root moves given a score to choose between
+    pub lines: usize,
+    // in centipawns, 0 always plays the best move
+    pub temperature: u32
+}
+
+pub const LEVELS: [Level; 5] = [
+    Level { name: "beginner", depth: Some(1), nodes: None, lines: 8, temperature: 150 },
+    Level { name: "novice", depth: Some(2), nodes: None, lines: 6, temperature: 80 },
+    Level { name: "casual", depth: Some(3), nodes: Some(50_000), lines: 4, temperature: 40 },
+    Level { name: "club", depth: Some(5), nodes: Some(300_000), lines: 3, temperature: 15 },
+    Level { name: "full", depth: None, nodes: None, lines: 1, temperature: 0 }
+];
+
+pub const FULL_STRENGTH: Level = LEVELS[4];
+
+impl Level {
+    pub fn parse(name: &str) -> Result<Level, String> {
+        LEVELS.iter()
+            .find(|level| level.name.eq_ignore_ascii_case(name))
+            .cloned()
+            .ok_or_else(|| format!("unknown level: {}, expected one of {}", name, names(LEVELS.iter().map(|l| l.name))))
+    }
+
+    // the level's limits on top of the caller's, whichever is stricter
+    pub fn limit(&self, limits: Limits) -> Limits {
+        let stricter = |a: Option<u64>, b: Option<u64>| match (a, b) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b)
+        };
+        Limits {
+            depth: stricter(limits.depth.map(u64::from), self.depth.map(u64::from)).map(|d| d as u32),
+            nodes: stricter(limits.nodes, self.nodes),
+            ..limits
+        }
+    }
+
+    // a forced win is never thrown away
+    pub fn choose(&self, lines: &[PvLine], rng: &mut Rng) -> Option<Move> {
+        let best = lines.first()?;
+        if self.temperature == 0 || search::is_mate_score(best.score) {
+            return best.pv.first().cloned();
+        }
+        let weights: Vec<f64> = lines.iter()
+            .map(|line| (f64::from(line.score - best.score) / f64::from(self.temperature)).exp())
+            .collect();
+        let mut pick = rng.next_f64() * weights.iter().sum::<f64>();
+        for (line, weight) in lines.iter().zip(weights) {
+            if pick < weight {
+                return line.pv.first().cloned();
+            }
+            pick -= weight;
+        }
+        best.pv.first().cloned()
+    }
+}
+
+fn names<'a>(names: impl Iterator<Item = &'a str>) -> String {
+    names.collect::<Vec<_>>().join(", ")
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Style {
+    Balanced,
+    // pushes pawns at the queening hexes and goes after the king
+    Aggressive,
+    // keeps its fortresses armoured and its king covered
+    Defensive
+}
+
+pub const STYLES: [Style; 3] = [Style::Balanced, Style::Aggressive, Style::Defensive];
+
+impl Style {
+    pub fn name(self) -> &'static str {
+        match self {
+            Style::Balanced => "balanced",
+            Style::Aggressive => "aggressive",
+            Style::Defensive => "defensive"
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Style, String> {
+        STYLES.iter()
+            .find(|style| style.name().eq_ignore_ascii_case(name))
+            .cloned()
+            .ok_or_else(|| format!("unknown style: {}, expected one of {}", name, names(STYLES.iter().map(|s| s.name()))))
+    }
+
+    // shifts the weights the style cares about, the rest are left alone
+    pub fn apply(self, weights: &EvalWeights) -> EvalWeights {
+        let mut weights = weights.clone();
+        match self {
+            Style::Balanced => {},
+            Style::Aggressive => {
+                weights.pawn_advance *= 2;
+                weights.pawn_near_queening = weights.pawn_near_queening * 3 / 2;
+                weights.king_exposure = weights.king_exposure * 3 / 2;
+                weights.king_shelter /= 2;
+                weights.mobility += 1;
+            },
+            Style::Defensive => {
+                weights.pawn_advance /= 2;
+                weights.king_shelter *= 2;
+                weights.fortress_pressure *= 2;
+                weights.fortress_breached *= 2;
+                weights.material.fortress += 50;
+            }
+        }
+        weights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(from: usize, to: usize, score: i32) -> PvLine {
+        PvLine { score, pv: vec![Move { from, to }] }
+    }
+
+    #[test]
+    fn limits_take_the_stricter_of_each() {
+        let club = Level::parse("club").unwrap();
+        let limits = Limits { depth: Some(8), nodes: Some(1000), movetime: None };
+        let limited = club.limit(limits);
+        assert_eq!((limited.depth, limited.nodes), (Some(5), Some(1000)));
+        let limited = club.limit(Limits::default());
+        assert_eq!((limited.depth, limited.nodes), (Some(5), Some(300_000)));
+        let limited = FULL_STRENGTH.limit(Limits { depth: Some(3), ..Default::default() });
+        assert_eq!((limited.depth, limited.nodes), (Some(3), None));
+    }
+
+    #[test]
+    fn the_best_line_wins_without_temperature_or_with_a_mate() {
+        let lines = vec![line(1, 2, 50), line(3, 4, 40), line(5, 6, -20)];
+        let mut rng = Rng::new(1);
+        for _ in 0..20 {
+            assert_eq!(FULL_STRENGTH.choose(&lines, &mut rng), Some(Move { from: 1, to: 2 }));
+        }
+        let mating = vec![line(1, 2, search::MATE - 3), line(3, 4, search::MATE - 5)];
+        for _ in 0..20 {
+            assert_eq!(LEVELS[0].choose(&mating, &mut rng), Some(Move { from: 1, to: 2 }));
+        }
+        assert_eq!(LEVELS[0].choose(&[], &mut rng), None);
+    }
+
+    #[test]
+    fn choices_stay_among_the_lines() {
+        let lines = vec![line(1, 2, 50), line(3, 4, 40), line(5, 6, -20)];
+        let firsts: Vec<Move> = lines.iter().map(|line| line.pv[0]).collect();
+        let mut rng = Rng::new(7);
+        let mut chosen = std::collections::HashSet::new();
+        for _ in 0..200 {
+            let m = LEVELS[0].choose(&lines, &mut rng).unwrap();
+            assert!(firsts.contains(&m));
+            chosen.insert(m);
+        }
+        // a beginner does not always play the best move
+        assert!(chosen.len() > 1);
+    }
+
+    #[test]
+    fn styles_are_named_in_any_case() {
+        assert_eq!(Style::parse("Aggressive").unwrap(), Style::Aggressive);
+        assert_eq!(Style::parse("DEFENSIVE").unwrap(), Style::Defensive);
+        assert!(Style::parse("reckless").is_err());
+        assert_eq!(Level::parse("Casual").unwrap().name, "casual");
+    }
+
+    #[test]
+    fn balanced_leaves_the_weights_alone() {
+        let weights = EvalWeights::default();
+        let balanced = Style::Balanced.apply(&weights);
+        assert_eq!(format!("{:?}", balanced), format!("{:?}", weights));
+        let aggressive = Style::Aggressive.apply(&weights);
+        assert!(aggressive.pawn_advance > weights.pawn_advance);
+    }
+}
@@ -0,0 +1,30 @@
+#![allow(dead_code)]
+pub mod agent;
+pub mod animation;
+pub mod annotation;
+pub mod app;
+pub mod board;
+pub mod book;
+pub mod eval;
+pub mod game_line;
+pub mod geometry;
+pub mod glyphs;
+pub mod level;
+pub mod logic;
+pub mod match_runner;
+pub mod mcts;
+pub mod notation;
+pub mod overlay;
+pub mod pieces;
+pub mod protocol;
+pub mod puzzle;
+pub mod record;
+pub mod rng;
+pub mod search;
+pub mod solver;
+pub mod see;
+pub mod tablebase;
+pub mod theme;
+pub mod tt;
+pub mod view;
+pub mod zobrist;
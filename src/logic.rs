@@ -7,43 +7,52 @@ fn is_valid_target(b: &Board, color: Player, t: usize) -> bool {
     && is_vulnerable(b, t)
 }
 
+// a fortress can only be taken once this many enemy pieces bear on it
+pub const FORTRESS_ARMOUR: u8 = 2;
+
 pub fn is_vulnerable(b: &Board, t: usize) -> bool {
     if let Some((_, piece)) = b.hexes[t] {
         if piece == Fortress {
-            return b.threats[t] > 1;
+            return b.threats[t] >= FORTRESS_ARMOUR;
         }
     }
     true
 }
 
 pub fn find_valid_moves(board: &Board, hex: usize) -> Vec<usize> {
-    match board.hexes[hex] {
+    piece_moves(&board.hexes, hex)
+}
+
+// moves of the piece on hex, captures included whatever the armour
+#[allow(clippy::redundant_closure, clippy::manual_filter)]
+pub fn piece_moves(hexes: &Hexes, hex: usize) -> Vec<usize> {
+    match hexes[hex] {
         Some((color, piece)) => {
             match piece {
                 King => {
-                    get_king_moves(board.hexes, hex)
+                    get_king_moves(*hexes, hex)
                 },
                 Bishop => {
-                    get_bishop_moves(board.hexes, hex)
+                    get_bishop_moves(*hexes, hex)
                 }
                 Queen => {
-                    get_queen_moves(board.hexes, hex)
+                    get_queen_moves(*hexes, hex)
                 },
                 Knight => {
-                    get_knight_moves(board.hexes, hex)
+                    get_knight_moves(*hexes, hex)
                 },
                 Fortress => {
-                    get_fortress_moves(board.hexes, hex)
+                    get_fortress_moves(*hexes, hex)
                 },
                 General => {
-                    get_general_moves(board.hexes,hex)
+                    get_general_moves(*hexes, hex)
                 }, 
                 Pawn => {
                     if color == Player::White {
                         let left = 
                             up_left(hex).and_then(|t|
-                                    if board.hexes[t].is_none()
-                                    || has_colored_piece(board.hexes, t, color.opposite()) {
+                                    if hexes[t].is_none()
+                                    || has_colored_piece(*hexes, t, color.opposite()) {
                                         Some(t)
                                     } else {
                                         None
@@ -51,24 +60,24 @@ pub fn find_valid_moves(board: &Board, hex: usize) -> Vec<usize> {
                             );
                         let right = 
                             up_right(hex).and_then(|t|
-                                    if board.hexes[t].is_none()
-                                    || has_colored_piece(board.hexes, t, color.opposite()) {
+                                    if hexes[t].is_none()
+                                    || has_colored_piece(*hexes, t, color.opposite()) {
                                         Some(t)
                                     } else {
                                         None
                                     }
                             );
                         let ul = if let Some(t) = up_left(hex) {
-                            board.hexes[t].is_none()
+                            hexes[t].is_none()
                         } else { false };
                         let ur = if let Some(t) = up_right(hex) {
-                            board.hexes[t].is_none()
+                            hexes[t].is_none()
                         } else { false };
                         let u: Option<usize> = up_right(hex).and_then(|t| up_left(t));
                         let up =
                             if ul && ur {
                                 if let Some(u) = u {
-                                    if board.hexes[u].is_none() {
+                                    if hexes[u].is_none() {
                                         Some(u)
                                     } else { None }
                                 } else { None }
@@ -78,8 +87,8 @@ pub fn find_valid_moves(board: &Board, hex: usize) -> Vec<usize> {
                     } else {
                         let left = 
                             down_left(hex).and_then(|t|
-                                    if board.hexes[t].is_none()
-                                    || has_colored_piece(board.hexes, t, color.opposite()) {
+                                    if hexes[t].is_none()
+                                    || has_colored_piece(*hexes, t, color.opposite()) {
                                         Some(t)
                                     } else {
                                         None
@@ -87,24 +96,24 @@ pub fn find_valid_moves(board: &Board, hex: usize) -> Vec<usize> {
                             );
                         let right = 
                             down_right(hex).and_then(|t|
-                                    if board.hexes[t].is_none()
-                                    || has_colored_piece(board.hexes, t, color.opposite()) {
+                                    if hexes[t].is_none()
+                                    || has_colored_piece(*hexes, t, color.opposite()) {
                                         Some(t)
                                     } else {
                                         None
                                     }
                             );
                             let dl = if let Some(t) = down_left(hex) {
-                                board.hexes[t].is_none()
+                                hexes[t].is_none()
                             } else { false };
                             let dr = if let Some(t) = down_right(hex) {
-                                board.hexes[t].is_none()
+                                hexes[t].is_none()
                             } else { false };
                             let d: Option<usize> = down_right(hex).and_then(|t| down_left(t));
                             let down =
                                 if dl && dr {
                                     if let Some(d) = d {
-                                        if board.hexes[d].is_none() {
+                                        if hexes[d].is_none() {
                                             Some(d)
                                         } else { None }
                                     } else { None }
@@ -143,6 +152,7 @@ pub fn get_king_moves(hexes: Hexes, hex: usize) -> Vec<usize> {
 //     out
 // }
 
+#[allow(clippy::manual_filter)]
 pub fn get_knight_moves(hexes: Hexes, hex: usize) -> Vec<usize> {
     if let Some((color, _)) = hexes[hex] {
         let knight_moves = 
@@ -340,3 +350,195 @@ pub fn count_threats(board: &Board) -> [u8; 91] {
     }
     out
 }
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Move {
+    pub from: usize,
+    pub to: usize
+}
+
+pub fn legal_moves(board: &Board) -> Vec<Move> {
+    let mut out = vec![];
+    for from in 0..HEX_COUNT {
+        if has_colored_piece(board.hexes, from, board.current_turn) {
+            let mut targets = find_valid_moves(board, from);
+            targets.retain(|t| is_vulnerable(board, *t));
+            // fortresses can list the same empty hex twice
+            targets.sort_unstable();
+            targets.dedup();
+            out.extend(targets.into_iter().map(|to| Move { from, to }));
+        }
+    }
+    out
+}
+
+pub fn is_legal(board: &Board, m: Move) -> bool {
+    has_colored_piece(board.hexes, m.from, board.current_turn)
+    && is_vulnerable(board, m.to)
+    && find_valid_moves(board, m.from).contains(&m.to)
+}
+
+pub fn make_move(board: &mut Board, m: Move) -> Option<(Player, crate::pieces::Piece)> {
+    let captured = board.hexes[m.to];
+    let moving = board.hexes[m.from];
+    board.hexes[m.to] = match moving {
+        Some((player, Pawn)) if is_queening_hex(m.to, player) => Some((player, Queen)),
+        _ => moving
+    };
+    board.hexes[m.from] = None;
+    board.current_turn = board.current_turn.opposite();
+    board.threats = count_threats(board);
+    captured
+}
+
+pub fn has_king(board: &Board, player: Player) -> bool {
+    board.hexes.contains(&Some((player, King)))
+}
+
+pub fn winner(board: &Board) -> Option<Player> {
+    if !has_king(board, Player::White) {
+        Some(Player::Black)
+    } else if !has_king(board, Player::Black) {
+        Some(Player::White)
+    } else {
+        None
+    }
+}
+
+// how many of player's pieces could capture on each hex if an enemy stood there
+pub fn attack_map(board: &Board, player: Player) -> [u8; HEX_COUNT] {
+    let mut out = [0; HEX_COUNT];
+    for hex in 0..HEX_COUNT {
+        let targets = match board.hexes[hex] {
+            Some((colour, Pawn)) if colour == player => {
+                let diagonals = match player {
+                    Player::White => [up_left(hex), up_right(hex)],
+                    Player::Black => [down_left(hex), down_right(hex)]
+                };
+                diagonals.iter().filter_map(|t| *t)
+                    .filter(|t| !has_colored_piece(board.hexes, *t, player))
+                    .collect()
+            },
+            Some((colour, Fortress)) if colour == player => {
+                get_king_moves(board.hexes, hex)
+            },
+            Some((colour, _)) if colour == player => {
+                find_valid_moves(board, hex)
+            },
+            _ => vec![]
+        };
+        for t in targets {
+            out[t] += 1;
+        }
+    }
+    out
+}
+
+// player's pieces that could capture on hex, whoever stands there now
+pub fn attackers(hexes: Hexes, hex: usize, player: Player) -> Vec<usize> {
+    let mut board = Board {
+        current_turn: player,
+        hexes,
+        positions: [[0.0, 0.0]; HEX_COUNT],
+        threats: [0; HEX_COUNT]
+    };
+    board.hexes[hex] = Some((player.opposite(), Pawn));
+    (0..HEX_COUNT)
+        .filter(|&h| has_colored_piece(board.hexes, h, player))
+        .filter(|&h| find_valid_moves(&board, h).contains(&hex))
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Won(Player),
+    Draw
+}
+
+// a game ends when a king is taken or the side to move is stuck
+pub fn outcome(board: &Board) -> Option<Outcome> {
+    if let Some(player) = winner(board) {
+        Some(Outcome::Won(player))
+    } else if legal_moves(board).is_empty() {
+        Some(Outcome::Draw)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Hexes, Player, HEX_COUNT};
+    use crate::pieces::Piece;
+
+    // the hex in the middle of the board
+    const CENTRE: usize = 45;
+
+    fn board_with(pieces: &[(usize, Player, Piece)], turn: Player) -> Board {
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        for &(hex, player, piece) in pieces {
+            hexes[hex] = Some((player, piece));
+        }
+        Board::from_hexes(hexes, turn)
+    }
+
+    #[test]
+    fn legal_moves_are_distinct_and_legal() {
+        let board = Board::new();
+        let moves = legal_moves(&board);
+        assert!(!moves.is_empty());
+        assert!(moves.iter().all(|m| is_legal(&board, *m)));
+        assert!(moves.iter().all(|m| has_colored_piece(board.hexes, m.from, Player::White)));
+        let distinct: std::collections::HashSet<Move> = moves.iter().cloned().collect();
+        assert_eq!(distinct.len(), moves.len());
+    }
+
+    #[test]
+    fn a_lone_king_steps_to_each_neighbour() {
+        let board = board_with(&[(CENTRE, Player::White, King), (90, Player::Black, King)], Player::White);
+        let mut targets: Vec<usize> = legal_moves(&board).iter().map(|m| m.to).collect();
+        targets.sort_unstable();
+        let mut neighbours = adjacent(CENTRE);
+        neighbours.sort_unstable();
+        assert_eq!(targets, neighbours);
+    }
+
+    #[test]
+    fn an_armoured_fortress_cannot_be_taken() {
+        let fortress = CENTRE;
+        let board = board_with(&[
+            (fortress, Player::Black, Fortress),
+            (up_left(fortress).unwrap(), Player::White, Queen),
+            (87, Player::White, King),
+            (3, Player::Black, King)
+        ], Player::White);
+        assert_eq!(board.threats[fortress], 1);
+        assert!(!legal_moves(&board).iter().any(|m| m.to == fortress));
+    }
+
+    #[test]
+    fn pawns_promote_on_the_far_rank() {
+        let board = board_with(&[(8, Player::White, Pawn), (87, Player::White, King), (40, Player::Black, King)], Player::White);
+        let promotion = legal_moves(&board).into_iter()
+            .find(|m| m.from == 8 && is_queening_hex(m.to, Player::White))
+            .unwrap();
+        let mut after = board.clone();
+        assert_eq!(make_move(&mut after, promotion), None);
+        assert_eq!(after.hexes[promotion.to], Some((Player::White, Queen)));
+        assert_eq!(after.hexes[8], None);
+        assert_eq!(after.current_turn, Player::Black);
+    }
+
+    #[test]
+    fn taking_the_king_wins() {
+        let king = CENTRE;
+        let queen = right(king).unwrap();
+        let board = board_with(&[(king, Player::Black, King), (queen, Player::White, Queen), (87, Player::White, King)], Player::White);
+        assert_eq!(winner(&board), None);
+        let mut after = board.clone();
+        assert_eq!(make_move(&mut after, Move { from: queen, to: king }), Some((Player::Black, King)));
+        assert_eq!(winner(&after), Some(Player::White));
+        assert_eq!(outcome(&after), Some(Outcome::Won(Player::White)));
+    }
+}
@@ -1,20 +1,81 @@
-#![allow(dead_code)]
-mod app;
-mod board;
-mod geometry;
-mod logic;
-mod pieces;
+use hexchess::{app, book, match_runner, puzzle, solver, tablebase};
 
+type Subcommand = fn(&[String]) -> Result<(), String>;
+
+fn usage() -> ! {
+    eprintln!("usage: hexchess [--engine \"<command> [args]\"] [--book FILE] [--tablebases DIR] [--puzzles FILE] [--theme NAME] [--record FILE] [--open FILE] [--values \"queen=900, ...\"] [--instant]");
+    eprintln!("       hexchess match ...");
+    eprintln!("       hexchess book ...");
+    eprintln!("       hexchess tablebase ...");
+    eprintln!("       hexchess solve ...");
+    eprintln!("       hexchess puzzles ...");
+    std::process::exit(2);
+}
 
 fn main() {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    let subcommand: Option<Subcommand> = match arguments.first().map(String::as_str) {
+        Some("match") => Some(match_runner::main),
+        Some("book") => Some(book::main),
+        Some("tablebase") => Some(tablebase::main),
+        Some("solve") => Some(solver::main),
+        Some("puzzles") => Some(puzzle::main),
+        _ => None
+    };
+    if let Some(command) = subcommand {
+        if let Err(e) = command(&arguments[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+        return;
+    }
+
+    let mut options = app::AppOptions::default();
+    let mut args = arguments.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--engine" => {
+                options.engine_command = Some(args.next().unwrap_or_else(|| usage()));
+            },
+            "--book" => {
+                options.book = Some(args.next().unwrap_or_else(|| usage()));
+            },
+            "--tablebases" => {
+                options.tablebases = Some(args.next().unwrap_or_else(|| usage()));
+            },
+            "--puzzles" => {
+                options.puzzles = Some(args.next().unwrap_or_else(|| usage()));
+            },
+            "--theme" => {
+                options.theme = Some(args.next().unwrap_or_else(|| usage()));
+            },
+            "--record" => {
+                options.record = Some(args.next().unwrap_or_else(|| usage()));
+            },
+            "--open" => {
+                options.open = Some(args.next().unwrap_or_else(|| usage()));
+            },
+            "--values" => {
+                options.piece_values = Some(args.next().unwrap_or_else(|| usage()));
+            },
+            "--instant" => options.instant = true,
+            _ => usage()
+        }
+    }
+
+    let window_mode = ggez::conf::WindowMode::default()
+        .dimensions(app::WINDOW[0], app::WINDOW[1])
+        .min_dimensions(app::MIN_WINDOW[0], app::MIN_WINDOW[1])
+        .resizable(true);
     let (mut ctx, event_loop) = ggez::ContextBuilder::new("my_game", "Cool Game Author")
+    .window_mode(window_mode)
     .build()
     .expect("aieee, could not create ggez context!");
 
     // Create an instance of your event handler.
     // Usually, you should provide it with the Context object to
     // use when setting your game up.
-    let my_game = app::App::new(&mut ctx);
+    let my_game = app::App::new(&mut ctx, options);
     
     // Run!
     ggez::event::run(ctx, event_loop, my_game);
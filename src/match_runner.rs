@@ -0,0 +1,373 @@
+use std::sync::atomic::AtomicBool;
+use crate::agent::{self, Agent};
+use crate::board::{Board, Player};
+use crate::eval::EvalWeights;
+use crate::logic::{self, Outcome};
+use crate::protocol;
+use crate::record::{self, GameRecord};
+use crate::rng::Rng;
+
+pub const DEFAULT_MAX_PLIES: usize = 400;
+// the record tag counting random opening moves, see self_play_game
+pub const RANDOM_PLIES_TAG: &str = "RandomPlies";
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Tally {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32
+}
+
+impl Tally {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // mean points per game for the first player
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + 0.5 * self.draws as f64) / self.games().max(1) as f64
+    }
+
+    // variance of the points of a single game
+    fn variance(&self) -> f64 {
+        let n = self.games().max(1) as f64;
+        let p = self.score();
+        (self.wins as f64 * (1.0 - p).powi(2)
+            + self.draws as f64 * (0.5 - p).powi(2)
+            + self.losses as f64 * p.powi(2)) / n
+    }
+
+    // Elo difference and its 95% margin, None until both sides have scored
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let p = self.score();
+        if self.games() == 0 || p <= 0.0 || p >= 1.0 {
+            return None;
+        }
+        let standard_error = (self.variance() / self.games() as f64).sqrt();
+        let low = elo_from_score((p - 1.96 * standard_error).max(1e-6));
+        let high = elo_from_score((p + 1.96 * standard_error).min(1.0 - 1e-6));
+        Some((elo_from_score(p), (high - low) / 2.0))
+    }
+}
+
+pub fn elo_from_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+pub fn score_from_elo(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SprtResult {
+    AcceptH0,
+    AcceptH1,
+    Continue
+}
+
+impl Sprt {
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    // log likelihood ratio of elo1 against elo0, normal approximation
+    pub fn llr(&self, tally: &Tally) -> f64 {
+        let variance = tally.variance();
+        if tally.games() == 0 || variance <= 0.0 {
+            return 0.0;
+        }
+        let s0 = score_from_elo(self.elo0);
+        let s1 = score_from_elo(self.elo1);
+        tally.games() as f64 * (s1 - s0) * (2.0 * tally.score() - s0 - s1) / (2.0 * variance)
+    }
+
+    pub fn test(&self, tally: &Tally) -> SprtResult {
+        let (lower, upper) = self.bounds();
+        let llr = self.llr(tally);
+        if llr >= upper {
+            SprtResult::AcceptH1
+        } else if llr <= lower {
+            SprtResult::AcceptH0
+        } else {
+            SprtResult::Continue
+        }
+    }
+}
+
+// plays one game to the end or to max_plies, which counts as a draw
+pub fn play_game(start: &Board, white: &mut dyn Agent, black: &mut dyn Agent, max_plies: usize) -> GameRecord {
+    let stop = AtomicBool::new(false);
+    let mut board = start.clone();
+    let mut record = GameRecord::default();
+    if notation_of(start) != crate::notation::START_POSITION {
+        record.set_tag("Start", &notation_of(start));
+    }
+    white.new_game();
+    black.new_game();
+    while record.moves.len() < max_plies {
+        if let Some(outcome) = logic::outcome(&board) {
+            record.result = Some(outcome);
+            return record;
+        }
+        let player = board.current_turn;
+        let agent: &mut dyn Agent = match player {
+            Player::White => &mut *white,
+            Player::Black => &mut *black
+        };
+        match agent.choose_move(&board, &stop) {
+            Ok(Some(m)) if logic::is_legal(&board, m) => {
+                logic::make_move(&mut board, m);
+                record.moves.push(m);
+            },
+            // a player that cannot or will not move loses, and one that
+            // broke down says why in the record
+            reply => {
+                if let Err(e) = reply {
+                    record.set_tag("Termination", &e);
+                }
+                record.result = Some(Outcome::Won(player.opposite()));
+                return record;
+            }
+        }
+    }
+    record.result = logic::outcome(&board).or(Some(Outcome::Draw));
+    record
+}
+
+// a game with random opening moves so self-play does not repeat itself,
+// tagged so the random moves are kept out of the book
+pub fn self_play_game(player: &str, weights: &EvalWeights, random_plies: usize, rng: &mut Rng) -> Result<GameRecord, String> {
+    let mut white = agent::from_spec(player, weights)?;
+    let mut black = agent::from_spec(player, weights)?;
+    let mut board = Board::new();
+    let mut opening = vec![];
+    for _ in 0..random_plies {
+        let moves = logic::legal_moves(&board);
+        match rng.choose(&moves) {
+            Some(m) if logic::outcome(&board).is_none() => {
+                logic::make_move(&mut board, *m);
+                opening.push(*m);
+            },
+            _ => break
+        }
+    }
+    let game = play_game(&board, white.as_mut(), black.as_mut(), DEFAULT_MAX_PLIES);
+    let opening_plies = opening.len();
+    let mut record = GameRecord {
+        moves: opening,
+        result: game.result,
+        ..Default::default()
+    };
+    record.moves.extend(game.moves);
+    record.set_tag("White", player);
+    record.set_tag("Black", player);
+    if opening_plies > 0 {
+        record.set_tag(RANDOM_PLIES_TAG, &opening_plies.to_string());
+    }
+    Ok(record)
+}
+
+fn notation_of(board: &Board) -> String {
+    crate::notation::position_string(board)
+}
+
+pub struct MatchOptions {
+    pub first: String,
+    pub second: String,
+    pub games: u32,
+    pub openings: Vec<Board>,
+    pub max_plies: usize,
+    pub sprt: Option<Sprt>,
+    pub record: Option<String>
+}
+
+// one line per opening, "startpos" or a position string, either
+// optionally followed by "moves ...", and # for comments
+pub fn parse_openings(text: &str) -> Result<Vec<Board>, String> {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(protocol::parse_position_command)
+        .collect()
+}
+
+pub fn run(options: &MatchOptions) -> Result<Tally, String> {
+    let weights = EvalWeights::load()?;
+    let mut first = agent::from_spec(&options.first, &weights)?;
+    let mut second = agent::from_spec(&options.second, &weights)?;
+    let mut tally = Tally::default();
+    let mut records = vec![];
+    for game in 0..options.games {
+        // each opening is played twice with colours swapped
+        let opening = &options.openings[(game as usize / 2) % options.openings.len()];
+        let first_is_white = game % 2 == 0;
+        let mut record = if first_is_white {
+            play_game(opening, first.as_mut(), second.as_mut(), options.max_plies)
+        } else {
+            play_game(opening, second.as_mut(), first.as_mut(), options.max_plies)
+        };
+        let (white, black) = if first_is_white {
+            (&options.first, &options.second)
+        } else {
+            (&options.second, &options.first)
+        };
+        record.set_tag("White", white);
+        record.set_tag("Black", black);
+        let first_colour = if first_is_white { Player::White } else { Player::Black };
+        match record.result {
+            Some(Outcome::Won(player)) if player == first_colour => tally.wins += 1,
+            Some(Outcome::Won(_)) => tally.losses += 1,
+            _ => tally.draws += 1
+        }
+        println!(
+            "game {}: {} vs {}: {} after {} plies, {}",
+            game + 1, white, black, record::result_text(record.result), record.moves.len(), summary(&tally)
+        );
+        records.push(record);
+        if let Some(sprt) = options.sprt {
+            let verdict = sprt.test(&tally);
+            if verdict != SprtResult::Continue {
+                println!("SPRT: {:?}, LLR {:.2}", verdict, sprt.llr(&tally));
+                break;
+            }
+        }
+    }
+    if let Some(path) = &options.record {
+        std::fs::write(path, record::write_records(&records)).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    println!("{} vs {}", options.first, options.second);
+    println!("{}", summary(&tally));
+    if let Some(sprt) = options.sprt {
+        let (lower, upper) = sprt.bounds();
+        println!("SPRT elo0 {} elo1 {}: LLR {:.2} ({:.2}, {:.2})", sprt.elo0, sprt.elo1, sprt.llr(&tally), lower, upper);
+    }
+    Ok(tally)
+}
+
+pub fn summary(tally: &Tally) -> String {
+    let elo = match tally.elo() {
+        Some((elo, margin)) => format!("Elo {:+.1} +/- {:.1}", elo, margin),
+        None => "Elo n/a".to_string()
+    };
+    format!("W {} D {} L {} ({:.1}%), {}", tally.wins, tally.draws, tally.losses, 100.0 * tally.score(), elo)
+}
+
+pub const USAGE: &str = "usage: hexchess match --first \"<player>\" --second \"<player>\" [--games N] \
+[--openings FILE] [--max-plies N] [--sprt ELO0,ELO1[,ALPHA,BETA]] [--record FILE]";
+
+pub fn main(args: &[String]) -> Result<(), String> {
+    let mut options = MatchOptions {
+        first: String::new(),
+        second: String::new(),
+        games: 10,
+        openings: vec![Board::new()],
+        max_plies: DEFAULT_MAX_PLIES,
+        sprt: None,
+        record: None
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--first" => options.first = value()?,
+            "--second" => options.second = value()?,
+            "--games" => options.games = value()?.parse().map_err(|_| "--games needs a number")?,
+            "--max-plies" => options.max_plies = value()?.parse().map_err(|_| "--max-plies needs a number")?,
+            "--openings" => {
+                let path = value()?;
+                let text = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+                options.openings = parse_openings(&text)?;
+            },
+            "--sprt" => {
+                let numbers: Vec<f64> = value()?.split(',')
+                    .map(|n| n.trim().parse::<f64>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| "--sprt needs numbers")?;
+                options.sprt = match numbers.as_slice() {
+                    [elo0, elo1] => Some(Sprt { elo0: *elo0, elo1: *elo1, alpha: 0.05, beta: 0.05 }),
+                    [elo0, elo1, alpha, beta] => Some(Sprt { elo0: *elo0, elo1: *elo1, alpha: *alpha, beta: *beta }),
+                    _ => return Err("--sprt takes ELO0,ELO1 or ELO0,ELO1,ALPHA,BETA".to_string())
+                };
+            },
+            "--record" => options.record = Some(value()?),
+            _ => return Err(USAGE.to_string())
+        }
+    }
+    if options.first.is_empty() || options.second.is_empty() || options.openings.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    run(&options).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tally(wins: u32, draws: u32, losses: u32) -> Tally {
+        Tally { wins, draws, losses }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn elo_and_score_are_inverses() {
+        for elo in [-300.0, -50.0, 0.0, 25.0, 400.0] {
+            assert!(close(elo_from_score(score_from_elo(elo)), elo));
+        }
+        assert!(close(score_from_elo(0.0), 0.5));
+        assert!(close(elo_from_score(10.0 / 11.0), 400.0));
+    }
+
+    #[test]
+    fn elo_margin_comes_from_the_spread_of_results() {
+        // 60 wins and 40 losses: a score of 0.6, each game's points varying
+        // by 0.24, so a standard error of sqrt(0.24 / 100)
+        let (elo, margin) = tally(60, 0, 40).elo().unwrap();
+        assert!(close(elo, 70.4365));
+        assert!(close(margin, 70.5725));
+        // draws instead of the same points in wins and losses narrow it
+        let (drawn_elo, drawn_margin) = tally(20, 80, 0).elo().unwrap();
+        assert!(close(drawn_elo, 70.4365));
+        assert!(drawn_margin < margin);
+        assert!(tally(5, 0, 0).elo().is_none());
+        assert!(tally(0, 0, 0).elo().is_none());
+    }
+
+    #[test]
+    fn sprt_log_likelihood_ratio() {
+        let sprt = Sprt { elo0: 0.0, elo1: 10.0, alpha: 0.05, beta: 0.05 };
+        let (lower, upper) = sprt.bounds();
+        assert!(close(lower, -2.9444));
+        assert!(close(upper, 2.9444));
+        // n (s1 - s0) (2 score - s0 - s1) / (2 variance)
+        assert!(close(sprt.llr(&tally(60, 0, 40)), 0.5563));
+        assert_eq!(sprt.llr(&tally(0, 0, 0)), 0.0);
+        assert_eq!(sprt.test(&tally(60, 0, 40)), SprtResult::Continue);
+        assert_eq!(sprt.test(&tally(600, 0, 400)), SprtResult::AcceptH1);
+        assert_eq!(sprt.test(&tally(400, 0, 600)), SprtResult::AcceptH0);
+    }
+
+    #[test]
+    fn self_play_tags_its_random_opening() {
+        let weights = EvalWeights::default();
+        let game = self_play_game("alphabeta depth=1", &weights, 4, &mut Rng::new(7)).unwrap();
+        assert_eq!(game.tag(RANDOM_PLIES_TAG), Some("4"));
+        assert_eq!(game.tag("White"), Some("alphabeta depth=1"));
+        assert!(game.moves.len() > 4);
+        assert_eq!(game.boards().unwrap().len(), game.moves.len() + 1);
+        let again = self_play_game("alphabeta depth=1", &weights, 4, &mut Rng::new(7)).unwrap();
+        assert_eq!(again.moves[..4], game.moves[..4]);
+        let unrandomised = self_play_game("alphabeta depth=1", &weights, 0, &mut Rng::new(7)).unwrap();
+        assert_eq!(unrandomised.tag(RANDOM_PLIES_TAG), None);
+    }
+}
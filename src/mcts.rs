@@ -0,0 +1,282 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use crate::agent::Agent;
+use crate::board::{Board, Player};
+use crate::eval::piece_value;
+use crate::logic::{self, Move, Outcome};
+use crate::pieces::Piece;
+use crate::rng::Rng;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Playout {
+    // uniformly random moves
+    Random,
+    // take the king when possible, prefer captures, score unfinished games by material
+    Heuristic
+}
+
+#[derive(Clone, Debug)]
+pub struct MctsOptions {
+    pub iterations: Option<u32>,
+    pub movetime: Option<Duration>,
+    pub playout: Playout,
+    // unfinished playouts are adjudicated after this many plies
+    pub max_playout_plies: u32,
+    pub exploration: f64,
+    // with an iteration budget the same seed always picks the same move
+    pub seed: u64
+}
+
+impl Default for MctsOptions {
+    fn default() -> MctsOptions {
+        MctsOptions {
+            iterations: None,
+            movetime: Some(Duration::from_millis(1500)),
+            playout: Playout::Heuristic,
+            max_playout_plies: 120,
+            exploration: 1.4,
+            seed: 0x6d63_7473
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MctsResult {
+    pub best_move: Move,
+    pub visits: u32,
+    // expected score of best_move for the side to move, 0 to 1
+    pub value: f64,
+    pub iterations: u32
+}
+
+struct Node {
+    m: Option<Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Move>,
+    // the player who made m, rewards are from their point of view
+    mover: Player,
+    visits: u32,
+    reward: f64
+}
+
+pub struct Mcts {
+    pub options: MctsOptions,
+    rng: Rng
+}
+
+impl Mcts {
+    pub fn new(options: MctsOptions) -> Mcts {
+        let rng = Rng::new(options.seed);
+        Mcts { options, rng }
+    }
+
+    pub fn search(&mut self, board: &Board, stop: &AtomicBool) -> Option<MctsResult> {
+        let start = Instant::now();
+        let root_moves = logic::legal_moves(board);
+        if root_moves.is_empty() {
+            return None;
+        }
+        let mut nodes = vec![Node {
+            m: None,
+            parent: None,
+            children: vec![],
+            untried: root_moves,
+            mover: board.current_turn.opposite(),
+            visits: 0,
+            reward: 0.0
+        }];
+        let mut iterations = 0;
+        loop {
+            let out_of_iterations = self.options.iterations.is_some_and(|n| iterations >= n);
+            let out_of_time = self.options.movetime.is_some_and(|t| start.elapsed() >= t);
+            // unlimited budgets run until stopped, but always do some work
+            if iterations > 0 && (out_of_iterations || out_of_time || stop.load(Ordering::Relaxed)) {
+                break;
+            }
+            self.iterate(board, &mut nodes);
+            iterations += 1;
+        }
+        let best = nodes[0].children.iter()
+            .cloned()
+            .max_by_key(|&c| nodes[c].visits)?;
+        Some(MctsResult {
+            best_move: nodes[best].m.unwrap(),
+            visits: nodes[best].visits,
+            value: nodes[best].reward / nodes[best].visits.max(1) as f64,
+            iterations
+        })
+    }
+
+    fn iterate(&mut self, root: &Board, nodes: &mut Vec<Node>) {
+        let mut board = root.clone();
+        let mut current = 0;
+        let mut finished = None;
+        // selection
+        while nodes[current].untried.is_empty() && !nodes[current].children.is_empty() {
+            current = self.select_child(nodes, current);
+            let m = nodes[current].m.unwrap();
+            logic::make_move(&mut board, m);
+            if let Some(player) = logic::winner(&board) {
+                finished = Some(Outcome::Won(player));
+                break;
+            }
+        }
+        // expansion
+        if finished.is_none() && !nodes[current].untried.is_empty() {
+            let index = self.rng.below(nodes[current].untried.len());
+            let m = nodes[current].untried.swap_remove(index);
+            logic::make_move(&mut board, m);
+            let untried = if logic::winner(&board).is_some() {
+                vec![]
+            } else {
+                logic::legal_moves(&board)
+            };
+            nodes.push(Node {
+                m: Some(m),
+                parent: Some(current),
+                children: vec![],
+                untried,
+                mover: board.current_turn.opposite(),
+                visits: 0,
+                reward: 0.0
+            });
+            let child = nodes.len() - 1;
+            nodes[current].children.push(child);
+            current = child;
+            finished = logic::winner(&board).map(Outcome::Won);
+        }
+        // simulation
+        let outcome = match finished {
+            Some(outcome) => Some(outcome),
+            None => self.playout(&mut board)
+        };
+        // backpropagation
+        let mut node = Some(current);
+        while let Some(index) = node {
+            let n = &mut nodes[index];
+            n.visits += 1;
+            n.reward += match outcome {
+                Some(Outcome::Won(player)) if player == n.mover => 1.0,
+                Some(Outcome::Won(_)) => 0.0,
+                _ => 0.5
+            };
+            node = n.parent;
+        }
+    }
+
+    fn select_child(&self, nodes: &[Node], parent: usize) -> usize {
+        let log_visits = (nodes[parent].visits.max(1) as f64).ln();
+        let uct = |child: usize| {
+            let n = &nodes[child];
+            let visits = n.visits.max(1) as f64;
+            n.reward / visits + self.options.exploration * (log_visits / visits).sqrt()
+        };
+        nodes[parent].children.iter()
+            .cloned()
+            .max_by(|a, b| uct(*a).partial_cmp(&uct(*b)).unwrap())
+            .unwrap()
+    }
+
+    // None is a draw
+    fn playout(&mut self, board: &mut Board) -> Option<Outcome> {
+        for _ in 0..self.options.max_playout_plies {
+            let moves = logic::legal_moves(board);
+            if moves.is_empty() {
+                return None;
+            }
+            let m = match self.options.playout {
+                Playout::Random => *self.rng.choose(&moves).unwrap(),
+                Playout::Heuristic => self.heuristic_move(board, &moves)
+            };
+            if let Some((_, Piece::King)) = logic::make_move(board, m) {
+                return Some(Outcome::Won(board.current_turn.opposite()));
+            }
+        }
+        match self.options.playout {
+            Playout::Random => None,
+            Playout::Heuristic => material_adjudication(board)
+        }
+    }
+
+    fn heuristic_move(&mut self, board: &Board, moves: &[Move]) -> Move {
+        let value = |m: &Move| board.hexes[m.to].map_or(0, |(_, piece)| piece_value(piece));
+        let best = moves.iter().cloned().max_by_key(value).unwrap();
+        // always take the king, usually take the biggest capture
+        if value(&best) >= piece_value(Piece::King) || (value(&best) > 0 && self.rng.below(4) != 0) {
+            best
+        } else {
+            *self.rng.choose(moves).unwrap()
+        }
+    }
+}
+
+fn material_adjudication(board: &Board) -> Option<Outcome> {
+    let mut balance = 0;
+    for (player, piece) in board.hexes.iter().flatten() {
+        match player {
+            Player::White => balance += piece_value(*piece),
+            Player::Black => balance -= piece_value(*piece)
+        }
+    }
+    if balance >= piece_value(Piece::Knight) {
+        Some(Outcome::Won(Player::White))
+    } else if balance <= -piece_value(Piece::Knight) {
+        Some(Outcome::Won(Player::Black))
+    } else {
+        None
+    }
+}
+
+impl Agent for Mcts {
+    fn name(&self) -> String {
+        "mcts".to_string()
+    }
+
+    fn choose_move(&mut self, board: &Board, stop: &AtomicBool) -> Result<Option<Move>, String> {
+        Ok(self.search(board, stop).map(|result| result.best_move))
+    }
+
+    fn new_game(&mut self) {
+        self.rng = Rng::new(self.options.seed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeded(seed: u64, playout: Playout) -> Mcts {
+        Mcts::new(MctsOptions {
+            iterations: Some(100),
+            movetime: None,
+            playout,
+            max_playout_plies: 40,
+            seed,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn the_same_seed_and_budget_pick_the_same_move() {
+        let board = Board::new();
+        let stop = AtomicBool::new(false);
+        for playout in [Playout::Random, Playout::Heuristic] {
+            let first = seeded(7, playout).search(&board, &stop).unwrap();
+            let again = seeded(7, playout).search(&board, &stop).unwrap();
+            assert_eq!(again.best_move, first.best_move);
+            assert_eq!(again.visits, first.visits);
+            assert_eq!(again.iterations, 100);
+        }
+    }
+
+    #[test]
+    fn a_new_game_restarts_the_sequence() {
+        let board = Board::new();
+        let stop = AtomicBool::new(false);
+        let mut mcts = seeded(11, Playout::Random);
+        let first = mcts.choose_move(&board, &stop).unwrap();
+        mcts.new_game();
+        assert_eq!(mcts.choose_move(&board, &stop).unwrap(), first);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::board::*;
+use crate::logic::{self, Move};
+use crate::pieces::{Piece, ALL_PIECES};
+
+// Hexes are named by a file letter, counted from the left of each rank,
+// and a rank number, counted from White's side: "a1" is the left end of
+// White's back rank and "f11" the right end of Black's.
+
+pub const START_POSITION: &str = "pbqkbp/1pnfnp1/2pggp2/3ppp3/10/11/10/3PPP3/2PGGP2/1PNFNP1/PBKQBP w";
+
+pub fn hex_name(hex: usize) -> String {
+    let file = (b'a' + get_file(hex) as u8) as char;
+    format!("{}{}", file, RANK_LENGTHS.len() - get_rank(hex))
+}
+
+pub fn parse_hex(text: &str) -> Option<usize> {
+    let mut chars = text.chars();
+    let file = chars.next()?;
+    if !file.is_ascii_lowercase() {
+        return None;
+    }
+    let file = (file as u8 - b'a') as usize;
+    let rank_number: usize = chars.as_str().parse().ok()?;
+    if rank_number == 0 || rank_number > RANK_LENGTHS.len() {
+        return None;
+    }
+    let rank = RANK_LENGTHS.len() - rank_number;
+    if file >= RANK_LENGTHS[rank] {
+        return None;
+    }
+    Some(RANK_LENGTHS[..rank].iter().sum::<usize>() + file)
+}
+
+// "c1d3", no separators, promotion is implied by the target hex
+pub fn move_text(m: Move) -> String {
+    format!("{}{}", hex_name(m.from), hex_name(m.to))
+}
+
+pub fn parse_move(text: &str) -> Option<Move> {
+    let text = text.trim();
+    // the second hex starts at the second letter
+    let split = text.char_indices().skip(1).find(|(_, c)| c.is_ascii_lowercase())?.0;
+    let from = parse_hex(&text[..split])?;
+    let to = parse_hex(&text[split..])?;
+    Some(Move { from, to })
+}
+
+pub fn parse_legal_move(board: &Board, text: &str) -> Result<Move, String> {
+    let m = parse_move(text).ok_or_else(|| format!("not a move: {}", text))?;
+    if logic::is_legal(board, m) {
+        Ok(m)
+    } else {
+        Err(format!("illegal move: {}", text))
+    }
+}
+
+// "Nc1-d3", "Nc1xd3", "Pe10-e11=Q", for people rather than programs
+pub fn describe_move(board: &Board, m: Move) -> String {
+    let (player, piece) = match board.hexes[m.from] {
+        Some(contents) => contents,
+        None => return move_text(m)
+    };
+    let separator = if board.hexes[m.to].is_some() { 'x' } else { '-' };
+    let promotion = if piece == Piece::Pawn && is_queening_hex(m.to, player) { "=Q" } else { "" };
+    format!("{}{}{}{}{}", piece.code(), hex_name(m.from), separator, hex_name(m.to), promotion)
+}
+
+// what describe_move writes or move_text, as typed by a person: "Nc1-d3",
+// "c1xd3", "c1d3"
+pub fn parse_described_move(board: &Board, text: &str) -> Result<Move, String> {
+    let text = text.trim();
+    let (piece, rest) = match text.chars().next() {
+        Some(letter) if letter.is_ascii_uppercase() => {
+            let piece = ALL_PIECES.iter().find(|p| p.code() == letter).ok_or_else(|| format!("not a piece: {}", letter))?;
+            (Some(*piece), &text[1..])
+        },
+        _ => (None, text)
+    };
+    let rest = rest.strip_suffix("=Q").unwrap_or(rest);
+    let hexes: String = rest.chars().filter(|c| !matches!(c, '-' | 'x')).collect();
+    let m = parse_move(&hexes).ok_or_else(|| format!("not a move: {}", text))?;
+    match (piece, board.hexes[m.from]) {
+        (Some(piece), Some((_, moved))) if piece != moved => Err(format!("no {} on {}", piece, hex_name(m.from))),
+        _ if logic::is_legal(board, m) => Ok(m),
+        _ => Err(format!("illegal move: {}", text))
+    }
+}
+
+fn piece_letter(player: Player, piece: Piece) -> char {
+    match player {
+        Player::White => piece.code(),
+        Player::Black => piece.code().to_ascii_lowercase()
+    }
+}
+
+// Ranks from Black's side down to White's, separated by '/', white
+// pieces in capitals, runs of empty hexes as numbers, then w or b for
+// the side to move.
+pub fn position_string(board: &Board) -> String {
+    let mut ranks = vec![];
+    let mut hex = 0;
+    for &len in RANK_LENGTHS.iter() {
+        let mut rank = String::new();
+        let mut empty = 0;
+        for _ in 0..len {
+            match board.hexes[hex] {
+                Some((player, piece)) => {
+                    if empty > 0 {
+                        rank.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    rank.push(piece_letter(player, piece));
+                },
+                None => empty += 1
+            }
+            hex += 1;
+        }
+        if empty > 0 {
+            rank.push_str(&empty.to_string());
+        }
+        ranks.push(rank);
+    }
+    let turn = match board.current_turn {
+        Player::White => 'w',
+        Player::Black => 'b'
+    };
+    format!("{} {}", ranks.join("/"), turn)
+}
+
+pub fn parse_position(text: &str) -> Result<Board, String> {
+    let mut parts = text.split_whitespace();
+    let layout = parts.next().ok_or("empty position")?;
+    let turn = match parts.next() {
+        Some("w") | None => Player::White,
+        Some("b") => Player::Black,
+        Some(other) => return Err(format!("bad side to move: {}", other))
+    };
+    let ranks: Vec<&str> = layout.split('/').collect();
+    if ranks.len() != RANK_LENGTHS.len() {
+        return Err(format!("expected {} ranks, found {}", RANK_LENGTHS.len(), ranks.len()));
+    }
+    let mut hexes: Hexes = [None; HEX_COUNT];
+    let mut hex = 0;
+    for (rank, text) in ranks.iter().enumerate() {
+        let mut filled = 0;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if let Some(digit) = c.to_digit(10) {
+                let too_many = || format!("rank {} has more than {} hexes", RANK_LENGTHS.len() - rank, RANK_LENGTHS[rank]);
+                let mut count = digit as usize;
+                // stopped as soon as it runs past the rank so it cannot overflow
+                while let Some(next) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    if filled + count > RANK_LENGTHS[rank] {
+                        return Err(too_many());
+                    }
+                    count = count * 10 + next as usize;
+                    chars.next();
+                }
+                filled += count;
+            } else {
+                let piece = ALL_PIECES.iter()
+                    .find(|p| p.code() == c.to_ascii_uppercase())
+                    .ok_or_else(|| format!("unknown piece: {}", c))?;
+                let player = if c.is_ascii_uppercase() { Player::White } else { Player::Black };
+                if filled < RANK_LENGTHS[rank] {
+                    hexes[hex + filled] = Some((player, *piece));
+                }
+                filled += 1;
+            }
+        }
+        if filled != RANK_LENGTHS[rank] {
+            return Err(format!("rank {} has {} hexes, expected {}", RANK_LENGTHS.len() - rank, filled, RANK_LENGTHS[rank]));
+        }
+        hex += filled;
+    }
+    Ok(Board::from_hexes(hexes, turn))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_names_round_trip() {
+        for hex in 0..HEX_COUNT {
+            assert_eq!(parse_hex(&hex_name(hex)), Some(hex));
+        }
+        assert_eq!(hex_name(0), "a11");
+        assert_eq!(parse_hex("a12"), None);
+        assert_eq!(parse_hex("g11"), None);
+        assert_eq!(parse_hex("A1"), None);
+    }
+
+    #[test]
+    fn moves_parse_in_both_formats() {
+        let board = Board::new();
+        for m in logic::legal_moves(&board) {
+            assert_eq!(parse_move(&move_text(m)), Some(m));
+            assert_eq!(parse_legal_move(&board, &move_text(m)), Ok(m));
+            assert_eq!(parse_described_move(&board, &describe_move(&board, m)), Ok(m));
+        }
+        assert_eq!(parse_move("c10d11"), Some(Move { from: parse_hex("c10").unwrap(), to: parse_hex("d11").unwrap() }));
+        assert_eq!(parse_move("c1"), None);
+    }
+
+    #[test]
+    fn described_moves_are_checked() {
+        let board = Board::new();
+        let m = logic::legal_moves(&board)[0];
+        let (_, piece) = board.hexes[m.from].unwrap();
+        let other = ALL_PIECES.iter().find(|p| **p != piece).unwrap();
+        let wrong_piece = format!("{}{}", other.code(), move_text(m));
+        assert_eq!(parse_described_move(&board, &wrong_piece), Err(format!("no {} on {}", other, hex_name(m.from))));
+        assert!(parse_described_move(&board, "Xc1-d3").unwrap_err().starts_with("not a piece"));
+        assert!(parse_described_move(&board, "a11-a10").unwrap_err().starts_with("illegal move"));
+        assert!(parse_described_move(&board, "hello").is_err());
+    }
+
+    #[test]
+    fn position_strings_round_trip() {
+        let mut board = Board::new();
+        assert_eq!(position_string(&board), START_POSITION);
+        for _ in 0..6 {
+            let m = logic::legal_moves(&board)[0];
+            logic::make_move(&mut board, m);
+            let text = position_string(&board);
+            let parsed = parse_position(&text).unwrap();
+            assert_eq!(parsed.hexes, board.hexes);
+            assert_eq!(parsed.current_turn, board.current_turn);
+            assert_eq!(position_string(&parsed), text);
+        }
+        assert!(parse_position("6/7 w").is_err());
+        assert!(parse_position(&START_POSITION.replace(" w", " x")).is_err());
+        assert!(parse_position(&START_POSITION.replacen('p', "z", 1)).is_err());
+        let digits = format!("{}{}", "9".repeat(30), START_POSITION);
+        assert_eq!(parse_position(&digits).err().unwrap(), "rank 11 has more than 6 hexes");
+        assert!(parse_position(&START_POSITION.replacen('/', "99/", 1)).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use crate::board::{Board, Player, HEX_COUNT};
+use crate::logic::{self, FORTRESS_ARMOUR};
+use crate::pieces::Piece;
+
+// What the GUI can lay over the board to show who attacks what, mostly to
+// help with the Fortress rule: a fortress is armoured until FORTRESS_ARMOUR
+// enemy pieces bear on it, and only then can it be taken.
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Heat {
+    Off,
+    // how many of the side's pieces attack each hex
+    Side(Player)
+}
+
+impl Heat {
+    pub fn next(self) -> Heat {
+        match self {
+            Heat::Off => Heat::Side(Player::White),
+            Heat::Side(Player::White) => Heat::Side(Player::Black),
+            Heat::Side(Player::Black) => Heat::Off
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Heat::Off => "off",
+            Heat::Side(Player::White) => "White's attacks",
+            Heat::Side(Player::Black) => "Black's attacks"
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Armour {
+    Armoured,
+    Breached
+}
+
+// every fortress on the board and whether it can be taken
+pub fn fortresses(board: &Board) -> Vec<(usize, Armour)> {
+    (0..HEX_COUNT)
+        .filter(|hex| matches!(board.hexes[*hex], Some((_, Piece::Fortress))))
+        .map(|hex| {
+            let armour = if logic::is_vulnerable(board, hex) { Armour::Breached } else { Armour::Armoured };
+            (hex, armour)
+        })
+        .collect()
+}
+
+// how many of player's pieces bear on each hex, where attack_map leaves
+// out the hexes holding player's own pieces this counts their defenders
+pub fn heat_map(board: &Board, player: Player) -> [u8; HEX_COUNT] {
+    let mut heat = logic::attack_map(board, player);
+    for (hex, count) in heat.iter_mut().enumerate() {
+        if matches!(board.hexes[hex], Some((colour, _)) if colour == player) {
+            *count = logic::attackers(board.hexes, hex, player).len() as u8;
+        }
+    }
+    heat
+}
+
+// pieces of either side the other could take with nothing to take back
+pub fn undefended(board: &Board) -> Vec<usize> {
+    let white = heat_map(board, Player::White);
+    let black = heat_map(board, Player::Black);
+    (0..HEX_COUNT)
+        .filter(|hex| match board.hexes[*hex] {
+            Some((player, piece)) => {
+                let (own, enemy) = match player {
+                    Player::White => (white[*hex], black[*hex]),
+                    Player::Black => (black[*hex], white[*hex])
+                };
+                let needed = if piece == Piece::Fortress { FORTRESS_ARMOUR } else { 1 };
+                enemy >= needed && own == 0
+            },
+            None => false
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Hexes;
+
+    fn position(pieces: &[(usize, Player, Piece)]) -> Board {
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        for &(hex, player, piece) in pieces {
+            hexes[hex] = Some((player, piece));
+        }
+        Board::from_hexes(hexes, Player::Black)
+    }
+
+    #[test]
+    fn a_defended_piece_is_not_undefended() {
+        let board = position(&[
+            (44, Player::Black, Piece::Queen),
+            (45, Player::White, Piece::Knight),
+            (46, Player::White, Piece::Queen)
+        ]);
+        assert_eq!(heat_map(&board, Player::White)[45], 1);
+        assert_eq!(heat_map(&board, Player::Black)[45], 1);
+        assert!(!undefended(&board).contains(&45));
+    }
+
+    #[test]
+    fn an_attacked_piece_with_no_defenders_is_undefended() {
+        let board = position(&[
+            (44, Player::Black, Piece::Queen),
+            (45, Player::White, Piece::Knight)
+        ]);
+        assert_eq!(heat_map(&board, Player::White)[45], 0);
+        assert!(undefended(&board).contains(&45));
+        // nothing attacks the queen, so it is not
+        assert!(!undefended(&board).contains(&44));
+    }
+}
@@ -1,6 +1,6 @@
 
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum Piece {
     King,
     Queen,
@@ -11,6 +11,16 @@ pub enum Piece {
     Pawn
 }
 
+pub const ALL_PIECES: [Piece; 7] = [
+    Piece::King,
+    Piece::Queen,
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Fortress,
+    Piece::General,
+    Piece::Pawn
+];
+
 impl std::fmt::Display for Piece {
     fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(formatter, "{:?}", self)
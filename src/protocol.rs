@@ -0,0 +1,588 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use crate::agent::Agent;
+use crate::board::{Board, Player};
+use crate::eval::EvalWeights;
+use crate::level::{self, Level, Style, LEVELS, STYLES};
+use crate::logic::{self, Move};
+use crate::notation;
+use crate::rng::Rng;
+use crate::search::{self, Engine, Limits, SearchInfo, MATE};
+use crate::tablebase::Tablebase;
+
+// A line based protocol modelled on UCI. The GUI side sends
+//
+//   hxi                                    handshake, answered by id and option lines then hxiok
+//   isready                                answered by readyok
+//   setoption name <name> value <value>
+//   newgame
+//   position startpos|<position string> [moves <move> ...]
+//   go [depth <n>] [nodes <n>] [movetime <ms>] [wtime <ms> btime <ms> [winc <ms> binc <ms>]] [infinite]
+//   stop
+//   quit
+//
+// and the engine answers go with info lines and finally
+//
+//   info depth <n> score cp <n>|mate <moves> nodes <n> nps <n> time <ms> pv <move> ...
+//   bestmove <move>|none
+//
+// With the MultiPV option above one every line of an iteration gets its
+// own info line, starting "info multipv <k>".
+//
+// Positions and moves use the notation module's formats.
+
+pub const ENGINE_NAME: &str = "hexchess";
+pub const ENGINE_AUTHOR: &str = "the hexchess authors";
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn send(line: &str) {
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+pub fn score_text(score: i32) -> String {
+    if search::is_mate_score(score) {
+        // plies to moves, negative when being mated
+        let plies = MATE - score.abs();
+        let moves = (plies + 1) / 2;
+        format!("mate {}", if score > 0 { moves } else { -moves })
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+pub fn info_line(info: &SearchInfo) -> String {
+    let millis = info.elapsed.as_millis() as u64;
+    let nps = info.nodes * 1000 / millis.max(1);
+    let pv: Vec<String> = info.pv.iter().map(|m| notation::move_text(*m)).collect();
+    format!(
+        "info depth {} score {} nodes {} nps {} time {} pv {}",
+        info.depth, score_text(info.score), info.nodes, nps, millis, pv.join(" ")
+    )
+}
+
+pub fn info_lines(info: &SearchInfo) -> Vec<String> {
+    if info.lines.len() <= 1 {
+        return vec![info_line(info)];
+    }
+    info.lines.iter().enumerate()
+        .map(|(index, line)| {
+            let line = SearchInfo {
+                score: line.score,
+                pv: line.pv.clone(),
+                ..info.clone()
+            };
+            format!("info multipv {} {}", index + 1, &info_line(&line)["info ".len()..])
+        })
+        .collect()
+}
+
+// position startpos|<position string> [moves ...]
+pub fn parse_position_command(args: &str) -> Result<Board, String> {
+    let (position, moves) = match args.find("moves") {
+        Some(index) => (args[..index].trim(), Some(&args[index + "moves".len()..])),
+        None => (args.trim(), None)
+    };
+    let mut board = if position == "startpos" {
+        Board::new()
+    } else {
+        notation::parse_position(position)?
+    };
+    for text in moves.unwrap_or("").split_whitespace() {
+        let m = notation::parse_legal_move(&board, text)?;
+        logic::make_move(&mut board, m);
+    }
+    Ok(board)
+}
+
+pub fn parse_go_command(args: &str, turn: Player) -> Limits {
+    let mut limits = Limits::default();
+    let mut clock = [None; 2];
+    let mut increment = [0u64; 2];
+    let mut infinite = false;
+    let words: Vec<&str> = args.split_whitespace().collect();
+    let number = |i: usize| words.get(i + 1).and_then(|w| w.parse::<u64>().ok());
+    for (i, word) in words.iter().enumerate() {
+        match *word {
+            "depth" => limits.depth = number(i).map(|n| n as u32),
+            "movetime" => limits.movetime = number(i).map(Duration::from_millis),
+            "nodes" => limits.nodes = number(i),
+            "wtime" => clock[0] = number(i),
+            "btime" => clock[1] = number(i),
+            "winc" => increment[0] = number(i).unwrap_or(0),
+            "binc" => increment[1] = number(i).unwrap_or(0),
+            "infinite" => infinite = true,
+            _ => {}
+        }
+    }
+    // search until stop whatever else was given
+    if infinite {
+        return Limits::default();
+    }
+    let side = match turn {
+        Player::White => 0,
+        Player::Black => 1
+    };
+    if limits.movetime.is_none() {
+        if let Some(remaining) = clock[side] {
+            // spread the clock over the rest of the game, keep a margin
+            let budget = remaining / 30 + increment[side] / 2;
+            let budget = budget.min(remaining.saturating_sub(50)).max(10);
+            limits.movetime = Some(Duration::from_millis(budget));
+        }
+    }
+    limits
+}
+
+// setoption name <name> value <value>, the value may be left out
+pub fn parse_setoption_command(args: &str) -> (&str, &str) {
+    let args = args.strip_prefix("name").unwrap_or(args).trim();
+    match args.find(" value") {
+        Some(index) => (args[..index].trim(), args[index + " value".len()..].trim()),
+        None => (args, "")
+    }
+}
+
+// A command line split into words the way a shell would for simple cases:
+// quotes keep spaces in a word, as in "C:\Program Files\engine.exe" or
+// '/opt/my engine/run', and a backslash outside quotes escapes the next
+// character.
+pub fn split_command(command: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some(close) if close == c => break,
+                        Some(inner) => word.push(inner),
+                        None => return Err(format!("unclosed quote in {}", command))
+                    }
+                }
+            },
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    word.get_or_insert_with(String::new).push(escaped);
+                }
+            },
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c)
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+struct RunningSearch {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Engine>
+}
+
+pub struct EngineServer {
+    // with the search thread while it runs
+    engine: Option<Engine>,
+    running: Option<RunningSearch>,
+    board: Board,
+    // the loaded weights, before the style shifts them
+    weights: EvalWeights,
+    level: Level,
+    style: Style,
+    // lines asked for with MultiPV, the level may search more
+    multi_pv: usize
+}
+
+impl Default for EngineServer {
+    fn default() -> EngineServer {
+        EngineServer::new()
+    }
+}
+
+impl EngineServer {
+    pub fn new() -> EngineServer {
+        let weights = EvalWeights::load().unwrap_or_else(|e| {
+            send(&format!("info string using default evaluation weights: {}", e));
+            EvalWeights::default()
+        });
+        EngineServer {
+            engine: Some(Engine::new(weights.clone())),
+            running: None,
+            board: Board::new(),
+            weights,
+            level: level::FULL_STRENGTH,
+            style: Style::Balanced,
+            multi_pv: 1
+        }
+    }
+
+    // commands that touch the engine cut a running search short rather
+    // than wait for it, the search still answers with its bestmove
+    fn engine(&mut self) -> &mut Engine {
+        self.stop_search();
+        self.engine.as_mut().unwrap()
+    }
+
+    fn stop_search(&mut self) {
+        if let Some(running) = self.running.take() {
+            running.stop.store(true, Ordering::Relaxed);
+            self.engine = Some(running.handle.join().expect("search thread panicked"));
+        }
+    }
+
+    fn option_lines(&mut self) -> Vec<String> {
+        let options = self.engine().options().clone();
+        let levels: Vec<String> = LEVELS.iter().map(|level| format!("var {}", level.name)).collect();
+        let styles: Vec<String> = STYLES.iter().map(|style| format!("var {}", style.name())).collect();
+        vec![
+            format!("option name Hash type spin default {} min 1 max 4096", options.hash_mb),
+            format!("option name Threads type spin default {} min 1 max {}", options.threads, search::MAX_THREADS),
+            format!("option name MultiPV type spin default {} min 1 max {}", self.multi_pv, search::MAX_MULTI_PV),
+            format!("option name Level type combo default {} {}", self.level.name, levels.join(" ")),
+            format!("option name Style type combo default {} {}", self.style.name(), styles.join(" ")),
+            "option name Tablebases type string default <empty>".to_string()
+        ]
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name.to_ascii_lowercase().as_str() {
+            "hash" => {
+                let mb = value.parse::<usize>().map_err(|e| e.to_string())?;
+                self.engine().set_hash_size(mb.clamp(1, 4096));
+                Ok(())
+            },
+            "threads" => {
+                let threads = value.parse::<usize>().map_err(|e| e.to_string())?;
+                self.engine().set_threads(threads);
+                Ok(())
+            },
+            "multipv" => {
+                let lines = value.parse::<usize>().map_err(|e| e.to_string())?;
+                self.multi_pv = lines.clamp(1, search::MAX_MULTI_PV);
+                let lines = self.multi_pv.max(self.level.lines);
+                self.engine().set_multi_pv(lines);
+                Ok(())
+            },
+            "level" => {
+                self.level = Level::parse(value)?;
+                let lines = self.multi_pv.max(self.level.lines);
+                self.engine().set_multi_pv(lines);
+                Ok(())
+            },
+            "style" => {
+                self.style = Style::parse(value)?;
+                let weights = self.style.apply(&self.weights);
+                self.engine().weights = weights;
+                Ok(())
+            },
+            "tablebases" => {
+                let tablebase = match value {
+                    "" | "<empty>" => None,
+                    dir => Some(Arc::new(Tablebase::load_dir(Path::new(dir))?))
+                };
+                self.engine().set_tablebase(tablebase);
+                Ok(())
+            },
+            _ => Err(format!("unknown option: {}", name))
+        }
+    }
+
+    fn go(&mut self, args: &str) {
+        self.stop_search();
+        let level = self.level;
+        let limits = level.limit(parse_go_command(args, self.board.current_turn));
+        let shown = self.multi_pv;
+        let mut engine = self.engine.take().unwrap();
+        let board = self.board.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let handle = std::thread::spawn(move || {
+            let info = engine.search(&board, limits, &thread_stop, |info| {
+                let mut info = info.clone();
+                info.lines.truncate(shown);
+                info_lines(&info).iter().for_each(|line| send(line));
+            });
+            match info.and_then(|info| level.choose(&info.lines, &mut Rng::from_time())) {
+                Some(m) => send(&format!("bestmove {}", notation::move_text(m))),
+                None => send("bestmove none")
+            }
+            engine
+        });
+        self.running = Some(RunningSearch { stop, handle });
+    }
+
+    // false once the GUI has asked us to quit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let line = line.trim();
+        let (command, args) = match line.find(' ') {
+            Some(index) => (&line[..index], line[index + 1..].trim()),
+            None => (line, "")
+        };
+        match command {
+            "" => {},
+            "hxi" => {
+                send(&format!("id name {}", ENGINE_NAME));
+                send(&format!("id author {}", ENGINE_AUTHOR));
+                for option in self.option_lines() {
+                    send(&option);
+                }
+                send("hxiok");
+            },
+            "isready" => send("readyok"),
+            "setoption" => {
+                let (name, value) = parse_setoption_command(args);
+                if let Err(e) = self.set_option(name, value) {
+                    send(&format!("info string {}", e));
+                }
+            },
+            "newgame" => {
+                self.engine().new_game();
+                self.board = Board::new();
+            },
+            "position" => {
+                self.stop_search();
+                match parse_position_command(args) {
+                    Ok(board) => self.board = board,
+                    Err(e) => send(&format!("info string {}", e))
+                }
+            },
+            "go" => self.go(args),
+            "stop" => self.stop_search(),
+            "quit" => {
+                self.stop_search();
+                return false;
+            },
+            _ => send(&format!("info string unknown command: {}", command))
+        }
+        true
+    }
+}
+
+pub fn run_engine<R: BufRead>(input: R) {
+    let mut server = EngineServer::new();
+    for line in input.lines() {
+        match line {
+            Ok(line) => {
+                if !server.handle(&line) {
+                    return;
+                }
+            },
+            Err(_) => break
+        }
+    }
+    server.stop_search();
+}
+
+// An engine in another process, driven over the protocol. It only ever
+// sees the current position, not the moves that led to it.
+pub struct ExternalEngine {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: mpsc::Receiver<String>,
+    pub movetime: Duration
+}
+
+impl ExternalEngine {
+    pub fn launch(command: &str, movetime: Duration) -> Result<ExternalEngine, String> {
+        let words = split_command(command)?;
+        let (program, args) = words.split_first().ok_or("no engine command given")?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("{}: {}", program, e))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => if sender.send(line).is_err() { break; },
+                    Err(_) => break
+                }
+            }
+        });
+        let mut engine = ExternalEngine {
+            name: program.to_string(),
+            child,
+            stdin,
+            lines,
+            movetime
+        };
+        engine.send("hxi")?;
+        loop {
+            let line = engine.lines.recv_timeout(HANDSHAKE_TIMEOUT)
+                .map_err(|_| format!("{} did not answer hxi", program))?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            } else if line.trim() == "hxiok" {
+                break;
+            }
+        }
+        Ok(engine)
+    }
+
+    pub fn send(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", line)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("{}: {}", self.name, e))
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        self.send(&format!("setoption name {} value {}", name, value))
+    }
+
+    // waits for bestmove, asking the engine to stop early if stop is raised
+    fn best_move(&mut self, stop: &AtomicBool) -> Result<Option<Move>, String> {
+        let mut stop_sent = false;
+        loop {
+            if !stop_sent && stop.load(Ordering::Relaxed) {
+                self.send("stop")?;
+                stop_sent = true;
+            }
+            match self.lines.recv_timeout(Duration::from_millis(20)) {
+                Ok(line) => {
+                    if let Some(m) = line.strip_prefix("bestmove") {
+                        let m = m.split_whitespace().next().unwrap_or("none");
+                        return Ok(notation::parse_move(m));
+                    }
+                },
+                Err(mpsc::RecvTimeoutError::Timeout) => {},
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(format!("{} exited", self.name));
+                }
+            }
+        }
+    }
+}
+
+impl Agent for ExternalEngine {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn choose_move(&mut self, board: &Board, stop: &AtomicBool) -> Result<Option<Move>, String> {
+        let position = format!("position {}", notation::position_string(board));
+        let go = format!("go movetime {}", self.movetime.as_millis());
+        self.send(&position)?;
+        self.send(&go)?;
+        match self.best_move(stop)? {
+            Some(m) if !logic::is_legal(board, m) => {
+                Err(format!("{} played an illegal move: {}", self.name, notation::move_text(m)))
+            },
+            m => Ok(m)
+        }
+    }
+
+    fn new_game(&mut self) {
+        let _ = self.send("newgame");
+    }
+}
+
+impl Drop for ExternalEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        for _ in 0..25 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_engine_paths_keep_their_spaces() {
+        let words = split_command("\"/opt/my engine/run\" --threads 2 'a b'c").unwrap();
+        assert_eq!(words, vec!["/opt/my engine/run", "--threads", "2", "a bc"]);
+        assert_eq!(split_command("my\\ engine  -x").unwrap(), vec!["my engine", "-x"]);
+        assert!(split_command("").unwrap().is_empty());
+        assert!(split_command("\"unclosed").is_err());
+    }
+
+    #[test]
+    fn go_reads_fixed_limits() {
+        let limits = parse_go_command("depth 6 nodes 5000", Player::White);
+        assert_eq!(limits.depth, Some(6));
+        assert_eq!(limits.nodes, Some(5000));
+        assert_eq!(limits.movetime, None);
+        let limits = parse_go_command("movetime 250 wtime 100000", Player::White);
+        assert_eq!(limits.movetime, Some(Duration::from_millis(250)));
+        let limits = parse_go_command("infinite wtime 100000 depth 3", Player::White);
+        assert_eq!((limits.depth, limits.movetime, limits.nodes), (None, None, None));
+    }
+
+    #[test]
+    fn go_budgets_the_clock_of_the_side_to_move() {
+        let args = "wtime 60000 btime 3000 winc 1000 binc 0";
+        let white = parse_go_command(args, Player::White).movetime.unwrap();
+        let black = parse_go_command(args, Player::Black).movetime.unwrap();
+        assert_eq!(white, Duration::from_millis(60000 / 30 + 500));
+        assert_eq!(black, Duration::from_millis(3000 / 30));
+        // never more than is left on the clock
+        let short = parse_go_command("btime 40", Player::Black).movetime.unwrap();
+        assert_eq!(short, Duration::from_millis(10));
+    }
+
+    #[test]
+    fn setoption_splits_name_and_value() {
+        assert_eq!(parse_setoption_command("name MultiPV value 3"), ("MultiPV", "3"));
+        assert_eq!(parse_setoption_command("name Tablebases value /data/my tables"), ("Tablebases", "/data/my tables"));
+        assert_eq!(parse_setoption_command("name Hash"), ("Hash", ""));
+    }
+
+    #[test]
+    fn options_are_checked_and_applied() {
+        let mut server = EngineServer::new();
+        assert!(server.set_option("MultiPV", "3").is_ok());
+        assert_eq!(server.multi_pv, 3);
+        assert!(server.set_option("multipv", "1000").is_ok());
+        assert_eq!(server.multi_pv, search::MAX_MULTI_PV);
+        assert!(server.set_option("Hash", "lots").is_err());
+        assert!(server.set_option("Level", "grandmaster").is_err());
+        assert!(server.set_option("Colour", "blue").is_err());
+        assert!(server.set_option("Level", LEVELS[0].name).is_ok());
+        assert_eq!(server.level.name, LEVELS[0].name);
+    }
+
+    #[test]
+    fn position_stops_a_running_search() {
+        let mut server = EngineServer::new();
+        server.handle("go infinite");
+        assert!(server.running.is_some());
+        let start = std::time::Instant::now();
+        assert!(server.handle("position startpos"));
+        assert!(server.running.is_none());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // the engine is back and takes options straight away
+        assert!(server.set_option("Hash", "8").is_ok());
+    }
+
+    #[test]
+    fn position_replays_its_moves() {
+        let board = parse_position_command("startpos").unwrap();
+        let m = logic::legal_moves(&board)[0];
+        let text = format!("startpos moves {}", notation::move_text(m));
+        let mut expected = board.clone();
+        logic::make_move(&mut expected, m);
+        let after = parse_position_command(&text).unwrap();
+        assert_eq!(notation::position_string(&after), notation::position_string(&expected));
+        assert!(parse_position_command("startpos moves a1a1").is_err());
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashSet;
+use std::sync::atomic::AtomicBool;
+use crate::board::{self, Board};
+use crate::eval::{self, EvalWeights};
+use crate::logic::{self, Move};
+use crate::match_runner;
+use crate::notation;
+use crate::pieces::Piece;
+use crate::record;
+use crate::rng::Rng;
+use crate::search::{self, Engine, Limits};
+use crate::see;
+use crate::solver;
+
+// Puzzle files have one puzzle per line, # for comments:
+//
+//   <position string>; <solution moves>; <themes>
+//   3k2/7/7Q/9/10/11/10/9/8/7/2K2p w; h9e9 d11c11 e9c11; mate-in-1
+//
+// The solution alternates the solver's moves with the opponent's forced
+// replies and ends on one of the solver's moves. A mate-in-n puzzle ends
+// with the king's capture on move n + 1.
+
+#[derive(Clone)]
+pub struct Puzzle {
+    pub board: Board,
+    pub line: Vec<Move>,
+    pub themes: Vec<String>
+}
+
+impl Puzzle {
+    pub fn to_line(&self) -> String {
+        let moves: Vec<String> = self.line.iter().map(|m| notation::move_text(*m)).collect();
+        format!("{}; {}; {}", notation::position_string(&self.board), moves.join(" "), self.themes.join(" "))
+    }
+
+    pub fn parse(line: &str) -> Result<Puzzle, String> {
+        let mut fields = line.split(';').map(str::trim);
+        let board = notation::parse_position(fields.next().unwrap_or_default())?;
+        let mut line = vec![];
+        let mut replay = board.clone();
+        for text in fields.next().unwrap_or_default().split_whitespace() {
+            let m = notation::parse_legal_move(&replay, text)?;
+            logic::make_move(&mut replay, m);
+            line.push(m);
+        }
+        if line.len() % 2 == 0 {
+            return Err("a puzzle has to end on the solver's move".to_string());
+        }
+        let themes = fields.next().unwrap_or_default().split_whitespace().map(String::from).collect();
+        Ok(Puzzle { board, line, themes })
+    }
+
+    pub fn board_at(&self, step: usize) -> Board {
+        let mut board = self.board.clone();
+        for m in self.line.iter().take(step) {
+            logic::make_move(&mut board, *m);
+        }
+        board
+    }
+
+    // the number in a mate-in-n theme
+    pub fn mate_in(&self) -> Option<u32> {
+        self.themes.iter().find_map(|theme| theme.strip_prefix("mate-in-")?.parse().ok())
+    }
+
+    // whether m is right at this point of the line, any move that still
+    // forces the king's capture in time solves a mate
+    pub fn accepts(&self, board: &Board, step: usize, m: Move) -> bool {
+        if self.line.get(step) == Some(&m) && self.board_at(step).hash() == board.hash() {
+            return true;
+        }
+        match self.mate_in() {
+            Some(n) => solver::wins_within(board, m, n + 1 - step as u32 / 2),
+            None => false
+        }
+    }
+}
+
+pub fn parse_puzzles(text: &str) -> Result<Vec<Puzzle>, String> {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .enumerate()
+        .map(|(number, line)| Puzzle::parse(line).map_err(|e| format!("puzzle {}: {}", number + 1, e)))
+        .collect()
+}
+
+pub fn write_puzzles(puzzles: &[Puzzle]) -> String {
+    puzzles.iter().map(|puzzle| puzzle.to_line() + "\n").collect()
+}
+
+pub fn load(path: &str) -> Result<Vec<Puzzle>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse_puzzles(&text).map_err(|e| format!("{}: {}", path, e))
+}
+
+#[derive(Clone, Debug)]
+pub struct GeneratorOptions {
+    // longest forced win looked for, in the solver's moves
+    pub mate_moves: u32,
+    // depth of the search that scores each move
+    pub depth: u32,
+    // centipawns the winning move must gain, and beat every other move by
+    pub min_gain: i32
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> GeneratorOptions {
+        GeneratorOptions {
+            mate_moves: 3,
+            depth: 4,
+            min_gain: 200
+        }
+    }
+}
+
+pub struct Generator {
+    pub options: GeneratorOptions,
+    engine: Engine,
+    seen: HashSet<u64>
+}
+
+// pieces other than the king, from the side to move's point of view
+fn material(board: &Board) -> i32 {
+    board.hexes.iter().flatten()
+        .filter(|(_, piece)| *piece != Piece::King)
+        .map(|(player, piece)| {
+            let value = eval::piece_value(*piece);
+            if *player == board.current_turn { value } else { -value }
+        })
+        .sum()
+}
+
+fn material_themes(board: &Board, m: Move) -> Vec<String> {
+    let mut themes = vec![];
+    let (player, piece) = board.hexes[m.from].unwrap();
+    match board.hexes[m.to] {
+        Some((_, captured)) => {
+            themes.push("capture".to_string());
+            if see::see(board, m) >= eval::piece_value(captured) {
+                themes.push("hanging-piece".to_string());
+            }
+            if captured == Piece::Fortress {
+                themes.push("fortress".to_string());
+            }
+        },
+        None => {
+            let mut after = board.clone();
+            logic::make_move(&mut after, m);
+            let targets = logic::find_valid_moves(&Board::from_hexes(after.hexes, player), m.to).into_iter()
+                .filter(|h| matches!(after.hexes[*h], Some((_, target)) if target == Piece::King || eval::piece_value(target) > eval::piece_value(piece)))
+                .count();
+            if targets >= 2 {
+                themes.push("fork".to_string());
+            } else if !(piece == Piece::Pawn && board::is_queening_hex(m.to, player)) {
+                themes.push("quiet-move".to_string());
+            }
+        }
+    }
+    if piece == Piece::Pawn && board::is_queening_hex(m.to, player) {
+        themes.push("promotion".to_string());
+    }
+    themes
+}
+
+impl Generator {
+    pub fn new(weights: EvalWeights, options: GeneratorOptions) -> Generator {
+        Generator {
+            options,
+            engine: Engine::new(weights),
+            seen: HashSet::new()
+        }
+    }
+
+    // a puzzle if exactly one move forces a win or wins material
+    pub fn find(&mut self, board: &Board) -> Option<Puzzle> {
+        if logic::outcome(board).is_some() || !self.seen.insert(board.hash()) {
+            return None;
+        }
+        let wins = solver::solve(board, self.options.mate_moves);
+        match wins.as_slice() {
+            // the king is hanging, that is no puzzle
+            [first, ..] if first.moves == 1 => None,
+            [only] => Some(Puzzle {
+                board: board.clone(),
+                line: only.tree.main_line(),
+                themes: vec![format!("mate-in-{}", only.moves - 1)]
+            }),
+            [] => self.material_puzzle(board),
+            _ => None
+        }
+    }
+
+    fn material_puzzle(&mut self, board: &Board) -> Option<Puzzle> {
+        let stop = AtomicBool::new(false);
+        let limits = Limits {
+            depth: Some(self.options.depth.max(2) - 1),
+            ..Default::default()
+        };
+        // one search rules out most positions before every move is scored
+        let info = self.engine.search(board, Limits { depth: Some(self.options.depth), ..limits }, &stop, |_| {})?;
+        if search::is_mate_score(info.score) || info.score - material(board) < self.options.min_gain {
+            return None;
+        }
+        let mut scored = vec![];
+        for m in logic::legal_moves(board) {
+            let mut child = board.clone();
+            logic::make_move(&mut child, m);
+            let info = self.engine.search(&child, limits, &stop, |_| {})?;
+            scored.push((-info.score, m, info.pv));
+        }
+        scored.sort_by_key(|(score, _, _)| -score);
+        let (best, m, reply) = scored.first()?;
+        let second = scored.get(1).map_or(-search::MATE, |(score, _, _)| *score);
+        if search::is_mate_score(*best) || best - material(board) < self.options.min_gain || best - second < self.options.min_gain {
+            return None;
+        }
+        // the line runs on to the solver's last capture in the next few moves
+        let mut line = vec![*m];
+        line.extend(reply.iter().take(4));
+        let mut replay = board.clone();
+        let mut end = 1;
+        for (ply, m) in line.iter().enumerate() {
+            if !logic::is_legal(&replay, *m) {
+                break;
+            }
+            if ply % 2 == 0 && replay.hexes[m.to].is_some() {
+                end = ply + 1;
+            }
+            logic::make_move(&mut replay, *m);
+        }
+        line.truncate(end);
+        Some(Puzzle {
+            board: board.clone(),
+            line,
+            themes: material_themes(board, *m)
+        })
+    }
+}
+
+pub const USAGE: &str = "usage: hexchess puzzles --output FILE [--records FILE]... [--self-play N] \
+[--player \"<player>\"] [--mate-moves N] [--depth N] [--min-gain N] [--seed N]";
+
+pub fn main(args: &[String]) -> Result<(), String> {
+    let mut output = None;
+    let mut record_files = vec![];
+    let mut self_play = 0;
+    let mut player = "alphabeta depth=3".to_string();
+    let mut options = GeneratorOptions::default();
+    let mut seed = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or_else(|| format!("{} needs a value", arg));
+        let number = |value: String| value.parse::<u64>().map_err(|_| format!("{} needs a number", arg));
+        match arg.as_str() {
+            "--output" => output = Some(value()?),
+            "--records" => record_files.push(value()?),
+            "--self-play" => self_play = number(value()?)?,
+            "--player" => player = value()?,
+            "--mate-moves" => options.mate_moves = number(value()?)? as u32,
+            "--depth" => options.depth = number(value()?)? as u32,
+            "--min-gain" => options.min_gain = number(value()?)? as i32,
+            "--seed" => seed = Some(number(value()?)?),
+            _ => return Err(USAGE.to_string())
+        }
+    }
+    let output = output.ok_or(USAGE)?;
+    let weights = EvalWeights::load()?;
+    let mut games = vec![];
+    for path in &record_files {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        games.extend(record::parse_records(&text).map_err(|e| format!("{}: {}", path, e))?);
+    }
+    let mut rng = seed.map_or_else(Rng::from_time, Rng::new);
+    for _ in 0..self_play {
+        games.push(match_runner::self_play_game(&player, &weights, 4, &mut rng)?);
+    }
+    let mut generator = Generator::new(weights, options);
+    let mut puzzles = vec![];
+    for (number, game) in games.iter().enumerate() {
+        let found = puzzles.len();
+        for board in game.boards()? {
+            puzzles.extend(generator.find(&board));
+        }
+        println!("game {}: {} puzzles", number + 1, puzzles.len() - found);
+    }
+    std::fs::write(&output, write_puzzles(&puzzles)).map_err(|e| format!("{}: {}", output, e))?;
+    println!("{}: {} puzzles", output, puzzles.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Hexes, Player, HEX_COUNT};
+
+    fn position(pieces: &[(usize, Player, Piece)], turn: Player) -> Board {
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        for &(hex, player, piece) in pieces {
+            hexes[hex] = Some((player, piece));
+        }
+        Board::from_hexes(hexes, turn)
+    }
+
+    // black mates the cornered white king with d10b10
+    fn mate_in_one() -> Board {
+        position(&[
+            (0, Player::White, Piece::King),
+            (2, Player::Black, Piece::King),
+            (9, Player::Black, Piece::Fortress)
+        ], Player::Black)
+    }
+
+    fn play(board: &Board, text: &str) -> Board {
+        let mut child = board.clone();
+        logic::make_move(&mut child, notation::parse_legal_move(board, text).unwrap());
+        child
+    }
+
+    #[test]
+    fn accepts_the_key_move_and_rejects_others() {
+        let puzzle = Puzzle::parse("K1k3/3f3/8/9/10/11/10/9/8/7/6 b; d10b10 a11a10 b10a10; mate-in-1").unwrap();
+        let board = puzzle.board.clone();
+        assert_eq!(puzzle.mate_in(), Some(1));
+        assert!(puzzle.accepts(&board, 0, notation::parse_move("d10b10").unwrap()));
+        let wrong = notation::parse_legal_move(&board, "c11d11").unwrap();
+        assert!(!puzzle.accepts(&board, 0, wrong));
+        // the other defence is met by another capture than the one in the line
+        let other = play(&play(&board, "d10b10"), "a11b11");
+        assert!(puzzle.accepts(&other, 2, notation::parse_move("c11b11").unwrap()));
+        assert!(!puzzle.accepts(&other, 2, notation::parse_move("b10c10").unwrap()));
+        // without a mate theme only the line itself is right
+        let mut material = puzzle.clone();
+        material.themes = vec!["capture".to_string()];
+        assert!(!material.accepts(&other, 2, notation::parse_move("c11b11").unwrap()));
+        assert!(material.accepts(&puzzle.board_at(2), 2, puzzle.line[2]));
+    }
+
+    #[test]
+    fn puzzle_lines_round_trip() {
+        let text = "K1k3/3f3/8/9/10/11/10/9/8/7/6 b; d10b10 a11a10 b10a10; mate-in-1\n";
+        let puzzles = parse_puzzles(&format!("# a comment\n\n{}", text)).unwrap();
+        assert_eq!(write_puzzles(&puzzles), text);
+        assert_eq!(
+            parse_puzzles("K1k3/3f3/8/9/10/11/10/9/8/7/6 b; d10b10 a11a10; mate-in-1").err().unwrap(),
+            "puzzle 1: a puzzle has to end on the solver's move"
+        );
+    }
+
+    #[test]
+    fn generates_mates_and_material_wins() {
+        let mut generator = Generator::new(EvalWeights::default(), GeneratorOptions::default());
+        let mate = generator.find(&mate_in_one()).unwrap();
+        assert_eq!(mate.to_line(), "K1k3/3f3/8/9/10/11/10/9/8/7/6 b; d10b10 a11a10 b10a10; mate-in-1");
+        // each position is only offered once
+        assert!(generator.find(&mate_in_one()).is_none());
+        let hanging_queen = position(&[
+            (87, Player::White, Piece::King),
+            (3, Player::Black, Piece::King),
+            (45, Player::White, Piece::Queen),
+            (15, Player::Black, Piece::Queen)
+        ], Player::White);
+        let capture = generator.find(&hanging_queen).unwrap();
+        assert_eq!(capture.line, vec![notation::parse_move("f6c9").unwrap()]);
+        assert_eq!(capture.themes, ["capture", "hanging-piece"]);
+        assert!(generator.find(&Board::new()).is_none());
+    }
+}
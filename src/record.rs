@@ -0,0 +1,286 @@
+use crate::board::{Board, Player};
+use crate::logic::{self, Move, Outcome};
+use crate::notation;
+
+// Game records are plain text, one game after another:
+//
+//   [White "alphabeta depth=4"]
+//   [Black "mcts iterations=500"]
+//   [Start "<position string>"]
+//   [Result "1-0"]
+//   c3c4 c9c8 {a comment} e2g4 ...
+//
+// Start is left out for games from the usual starting position, and the
+// moves may run over several lines. Comments in braces belong after the
+// move before them. A blank line or the next tag ends a game. A backslash
+// escapes the next character, so tag values can hold \" and comments \},
+// and a backslash itself is written \\.
+
+#[derive(Clone, Debug, Default)]
+pub struct GameRecord {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<Move>,
+    // after how many moves, and what it says
+    pub comments: Vec<(usize, String)>,
+    pub result: Option<Outcome>
+}
+
+pub fn result_text(result: Option<Outcome>) -> &'static str {
+    match result {
+        Some(Outcome::Won(Player::White)) => "1-0",
+        Some(Outcome::Won(Player::Black)) => "0-1",
+        Some(Outcome::Draw) => "1/2-1/2",
+        None => "*"
+    }
+}
+
+fn parse_result(text: &str) -> Option<Outcome> {
+    match text {
+        "1-0" => Some(Outcome::Won(Player::White)),
+        "0-1" => Some(Outcome::Won(Player::Black)),
+        "1/2-1/2" => Some(Outcome::Draw),
+        _ => None
+    }
+}
+
+impl GameRecord {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        self.tags.retain(|(key, _)| key != name);
+        self.tags.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn start_board(&self) -> Result<Board, String> {
+        match self.tag("Start") {
+            Some(position) => notation::parse_position(position),
+            None => Ok(Board::new())
+        }
+    }
+
+    // every position of the game, starting position first
+    pub fn boards(&self) -> Result<Vec<Board>, String> {
+        let mut board = self.start_board()?;
+        let mut boards = vec![board.clone()];
+        for (ply, m) in self.moves.iter().enumerate() {
+            if !logic::is_legal(&board, *m) {
+                return Err(format!("illegal move {} at ply {}", notation::move_text(*m), ply + 1));
+            }
+            logic::make_move(&mut board, *m);
+            boards.push(board.clone());
+        }
+        Ok(boards)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for (key, value) in &self.tags {
+            if key != "Result" {
+                out.push_str(&format!("[{} \"{}\"]\n", key, escape(value, '"')));
+            }
+        }
+        out.push_str(&format!("[Result \"{}\"]\n", result_text(self.result)));
+        let comments_after = |ply: usize| self.comments.iter()
+            .filter(move |(after, _)| *after == ply)
+            .map(|(_, text)| format!("{{{}}}", escape(text, '}')));
+        let mut words: Vec<String> = comments_after(0).collect();
+        for (ply, m) in self.moves.iter().enumerate() {
+            words.push(notation::move_text(*m));
+            words.extend(comments_after(ply + 1));
+        }
+        for line in words.chunks(16) {
+            out.push_str(&line.join(" "));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// a backslash before every backslash and closing character
+fn escape(text: &str, closing: char) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        if c == '\\' || c == closing {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+// "value" with escapes, nothing may follow the closing quote
+fn parse_tag_value(text: &str) -> Option<String> {
+    let mut chars = text.strip_prefix('"')?.chars();
+    let mut value = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.push(chars.next()?),
+            '"' => return Some(value).filter(|_| chars.as_str().is_empty()),
+            c => value.push(c)
+        }
+    }
+    None
+}
+
+// comment text up to an unescaped }, and whatever follows it if it
+// closes on this line
+fn read_comment<'a>(text: &mut String, line: &'a str) -> Option<&'a str> {
+    let mut chars = line.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next().map(|(_, escaped)| escaped)),
+            '}' => return Some(&line[index + 1..]),
+            c => text.push(c)
+        }
+    }
+    None
+}
+
+pub fn write_records(records: &[GameRecord]) -> String {
+    records.iter()
+        .map(|r| r.to_text())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+pub fn parse_records(text: &str) -> Result<Vec<GameRecord>, String> {
+    let mut records = vec![];
+    let mut current: Option<GameRecord> = None;
+    let mut in_moves = false;
+    // a comment carrying on from an earlier line
+    let mut comment: Option<String> = None;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let error = |e: String| format!("line {}: {}", number + 1, e);
+        if comment.is_some() {
+            let record = current.get_or_insert_with(GameRecord::default);
+            read_moves(record, line, &mut comment).map_err(error)?;
+        } else if line.is_empty() {
+            records.extend(current.take());
+            in_moves = false;
+        } else if let Some(tag) = line.strip_prefix('[') {
+            if in_moves {
+                records.extend(current.take());
+                in_moves = false;
+            }
+            let tag = tag.strip_suffix(']').ok_or_else(|| error("unclosed tag".to_string()))?;
+            let (key, value) = tag.split_once(' ').ok_or_else(|| error("tag without value".to_string()))?;
+            let value = parse_tag_value(value.trim()).ok_or_else(|| error(format!("bad value for tag {}", key)))?;
+            let record = current.get_or_insert_with(GameRecord::default);
+            if key == "Result" {
+                record.result = parse_result(&value);
+            }
+            record.set_tag(key, &value);
+        } else if !line.starts_with('#') {
+            in_moves = true;
+            let record = current.get_or_insert_with(GameRecord::default);
+            read_moves(record, line, &mut comment).map_err(error)?;
+        }
+    }
+    if comment.is_some() {
+        return Err("unclosed comment".to_string());
+    }
+    records.extend(current);
+    Ok(records)
+}
+
+fn read_moves(record: &mut GameRecord, line: &str, comment: &mut Option<String>) -> Result<(), String> {
+    let mut rest = line;
+    loop {
+        if let Some(text) = comment.as_mut() {
+            match read_comment(text, rest) {
+                Some(after) => {
+                    record.comments.push((record.moves.len(), text.trim().to_string()));
+                    *comment = None;
+                    rest = after;
+                },
+                None => {
+                    text.push(' ');
+                    return Ok(());
+                }
+            }
+        }
+        let (words, opened) = match rest.split_once('{') {
+            Some((words, after)) => (words, Some(after)),
+            None => (rest, None)
+        };
+        for word in words.split_whitespace() {
+            if parse_result(word).is_some() || word == "*" {
+                continue;
+            }
+            let m = notation::parse_move(word).ok_or_else(|| format!("not a move: {}", word))?;
+            record.moves.push(m);
+        }
+        match opened {
+            Some(after) => {
+                *comment = Some(String::new());
+                rest = after;
+            },
+            None => return Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> GameRecord {
+        let mut board = Board::new();
+        let mut record = GameRecord::default();
+        for _ in 0..20 {
+            let m = logic::legal_moves(&board)[0];
+            logic::make_move(&mut board, m);
+            record.moves.push(m);
+        }
+        record.result = Some(Outcome::Draw);
+        record
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let mut record = sample();
+        record.set_tag("White", "alphabeta depth=4");
+        record.set_tag("Black", "engine -- \"/opt/my engine\" [x] C:\\bin\\");
+        record.set_tag("Start", crate::notation::START_POSITION);
+        record.comments = vec![
+            (0, "before the first move".to_string()),
+            (3, "{braces} and \\backslashes\\".to_string()),
+            (20, "[%draw green e4]".to_string())
+        ];
+        let text = write_records(&[record.clone(), sample()]);
+        let parsed = parse_records(&text).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].tags, {
+            let mut tags = record.tags.clone();
+            tags.push(("Result".to_string(), "1/2-1/2".to_string()));
+            tags
+        });
+        assert_eq!(parsed[0].moves, record.moves);
+        assert_eq!(parsed[0].comments, record.comments);
+        assert_eq!(parsed[0].result, record.result);
+        assert_eq!(parsed[1].moves, sample().moves);
+        assert_eq!(parsed[0].to_text(), record.to_text());
+    }
+
+    #[test]
+    fn comments_can_run_over_lines() {
+        let text = "[Result \"*\"]\nc3c4 {one\ntwo} c9c8\n";
+        let parsed = parse_records(text).unwrap();
+        assert_eq!(parsed[0].comments, vec![(1, "one two".to_string())]);
+        assert_eq!(parsed[0].moves.len(), 2);
+    }
+
+    #[test]
+    fn broken_records_are_rejected() {
+        assert!(parse_records("[White \"unclosed]\n").is_err());
+        assert!(parse_records("[White \"a\" b]\n").is_err());
+        assert!(parse_records("[White]\n").is_err());
+        assert!(parse_records("c3c4 {never closed\n").is_err());
+        assert!(parse_records("c3c4 nonsense\n").is_err());
+    }
+}
@@ -0,0 +1,539 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::board::Board;
+use crate::eval::{self, EvalWeights};
+use crate::logic::{self, Move};
+use crate::pieces::Piece;
+use crate::see;
+use crate::tablebase::Tablebase;
+use crate::tt::{self, Bound, TranspositionTable};
+
+pub const MATE: i32 = 30000;
+pub const MAX_PLY: u32 = 64;
+const INFINITY: i32 = 32000;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    pub depth: Option<u32>,
+    pub movetime: Option<Duration>,
+    // counted over all threads
+    pub nodes: Option<u64>
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    pub depth: u32,
+    pub score: i32,
+    pub nodes: u64,
+    pub elapsed: Duration,
+    pub pv: Vec<Move>,
+    // the best few root moves, best first, the first is score and pv
+    pub lines: Vec<PvLine>
+}
+
+#[derive(Clone, Debug)]
+pub struct PvLine {
+    pub score: i32,
+    pub pv: Vec<Move>
+}
+
+impl SearchInfo {
+    pub fn best_move(&self) -> Option<Move> {
+        self.pv.first().cloned()
+    }
+}
+
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE - MAX_PLY as i32
+}
+
+#[derive(Clone, Debug)]
+pub struct EngineOptions {
+    pub hash_mb: usize,
+    // one thread searches deterministically, more share the table
+    pub threads: usize,
+    // root moves given a full line of their own
+    pub multi_pv: usize
+}
+
+pub const MAX_THREADS: usize = 256;
+pub const MAX_MULTI_PV: usize = 16;
+
+impl Default for EngineOptions {
+    fn default() -> EngineOptions {
+        EngineOptions {
+            hash_mb: tt::DEFAULT_HASH_MB,
+            threads: 1,
+            multi_pv: 1
+        }
+    }
+}
+
+pub struct Engine {
+    pub weights: EvalWeights,
+    options: EngineOptions,
+    tt: TranspositionTable,
+    tablebase: Option<Arc<Tablebase>>
+}
+
+struct Searcher<'a> {
+    weights: &'a EvalWeights,
+    tt: &'a TranspositionTable,
+    tablebase: Option<&'a Tablebase>,
+    stop: &'a AtomicBool,
+    // raised when the main thread finishes, only helpers look at it
+    finished: Option<&'a AtomicBool>,
+    deadline: Option<Instant>,
+    node_limit: Option<u64>,
+    nodes: u64,
+    multi_pv: usize,
+    // nodes of all threads, added to in batches
+    total_nodes: &'a AtomicU64,
+    // the first iteration is never interrupted so there is a move to play
+    interruptible: bool,
+    aborted: bool
+}
+
+impl Engine {
+    pub fn new(weights: EvalWeights) -> Engine {
+        Engine::with_options(weights, EngineOptions::default())
+    }
+
+    pub fn with_options(weights: EvalWeights, options: EngineOptions) -> Engine {
+        Engine {
+            weights,
+            tt: TranspositionTable::new(options.hash_mb),
+            options,
+            tablebase: None
+        }
+    }
+
+    pub fn options(&self) -> &EngineOptions {
+        &self.options
+    }
+
+    pub fn set_hash_size(&mut self, hash_mb: usize) {
+        if hash_mb != self.options.hash_mb {
+            self.options.hash_mb = hash_mb;
+            self.tt = TranspositionTable::new(hash_mb);
+        }
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.options.threads = threads.clamp(1, MAX_THREADS);
+    }
+
+    pub fn set_multi_pv(&mut self, lines: usize) {
+        self.options.multi_pv = lines.clamp(1, MAX_MULTI_PV);
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<Tablebase>>) {
+        self.tablebase = tablebase;
+    }
+
+    pub fn tt(&self) -> &TranspositionTable {
+        &self.tt
+    }
+
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+    }
+
+    // iterative deepening negamax, returns the last completed iteration
+    // of the main thread
+    //
+    // Extra threads run the same search on their own copies of the board
+    // (lazy SMP) and only help through the shared table. They start at
+    // different depths so they fill it with different positions.
+    pub fn search<F>(&mut self, board: &Board, limits: Limits, stop: &AtomicBool, mut on_info: F) -> Option<SearchInfo>
+    where F: FnMut(&SearchInfo) {
+        let start = Instant::now();
+        // positions in the tablebase need no search
+        if let Some((m, probe)) = self.tablebase.as_ref().and_then(|tb| tb.best_move(board)) {
+            let info = SearchInfo {
+                depth: 1,
+                score: probe.score(0),
+                nodes: 1,
+                elapsed: start.elapsed(),
+                pv: vec![m],
+                lines: vec![PvLine { score: probe.score(0), pv: vec![m] }]
+            };
+            on_info(&info);
+            return Some(info);
+        }
+        self.tt.new_search();
+        let deadline = limits.movetime.map(|t| Instant::now() + t);
+        let total_nodes = AtomicU64::new(0);
+        let finished = AtomicBool::new(false);
+        let helpers = self.options.threads.clamp(1, MAX_THREADS) - 1;
+        let weights = &self.weights;
+        let tt = &self.tt;
+        let tablebase = self.tablebase.as_deref();
+        let multi_pv = self.options.multi_pv.clamp(1, MAX_MULTI_PV);
+        let searcher = |helper: bool| Searcher {
+            weights,
+            tt,
+            tablebase,
+            stop,
+            finished: if helper { Some(&finished) } else { None },
+            deadline,
+            node_limit: limits.nodes,
+            nodes: 0,
+            // helpers only ever need the best move
+            multi_pv: if helper { 1 } else { multi_pv },
+            total_nodes: &total_nodes,
+            // helpers have no move to deliver and can stop at any time
+            interruptible: helper,
+            aborted: false
+        };
+        if helpers == 0 {
+            return searcher(false).run(board, limits, 1, on_info);
+        }
+        std::thread::scope(|scope| {
+            for helper in 0..helpers {
+                let board = board.clone();
+                let searcher = searcher(true);
+                let first_depth = 1 + (helper as u32 + 1) % 2;
+                scope.spawn(move || {
+                    searcher.run(&board, Limits { depth: None, ..limits }, first_depth, |_| {})
+                });
+            }
+            let result = searcher(false).run(board, limits, 1, on_info);
+            finished.store(true, Ordering::Relaxed);
+            result
+        })
+    }
+}
+
+impl<'a> Searcher<'a> {
+    fn run<F>(mut self, board: &Board, limits: Limits, first_depth: u32, mut on_info: F) -> Option<SearchInfo>
+    where F: FnMut(&SearchInfo) {
+        let start = Instant::now();
+        let mut root_moves = logic::legal_moves(board);
+        if root_moves.is_empty() {
+            return None;
+        }
+        order_moves(board, &mut root_moves);
+        let max_depth = limits.depth.unwrap_or(MAX_PLY).clamp(1, MAX_PLY);
+        let mut best: Option<SearchInfo> = None;
+        for depth in first_depth..=max_depth {
+            let lines = self.search_root(board, &mut root_moves, depth);
+            if self.aborted {
+                break;
+            }
+            let score = lines[0].score;
+            let info = SearchInfo {
+                depth,
+                score,
+                nodes: self.total_nodes.load(Ordering::Relaxed) + (self.nodes & 1023),
+                elapsed: start.elapsed(),
+                pv: lines[0].pv.clone(),
+                lines
+            };
+            on_info(&info);
+            best = Some(info);
+            self.interruptible = true;
+            if is_mate_score(score) && MATE - score.abs() <= depth as i32 {
+                break;
+            }
+        }
+        best
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if !self.interruptible {
+            return false;
+        }
+        if self.stop.load(Ordering::Relaxed) || self.finished.is_some_and(|f| f.load(Ordering::Relaxed)) {
+            self.aborted = true;
+        } else if self.nodes & 1023 == 0 {
+            if let Some(deadline) = self.deadline {
+                if Instant::now() >= deadline {
+                    self.aborted = true;
+                }
+            }
+            if let Some(limit) = self.node_limit {
+                if self.total_nodes.load(Ordering::Relaxed) >= limit {
+                    self.aborted = true;
+                }
+            }
+        }
+        self.aborted
+    }
+
+    fn count_node(&mut self) {
+        self.nodes += 1;
+        if self.nodes & 1023 == 0 {
+            self.total_nodes.fetch_add(1024, Ordering::Relaxed);
+        }
+    }
+
+    // With more than one line each is the best of the moves the earlier
+    // lines left over. The moves end up in the order of their lines, so
+    // the next iteration searches the best first.
+    fn search_root(&mut self, board: &Board, moves: &mut [Move], depth: u32) -> Vec<PvLine> {
+        let mut lines = vec![];
+        let mut child_pv = vec![];
+        for first in 0..self.multi_pv.min(moves.len()) {
+            let mut alpha = -INFINITY;
+            let mut best_index = first;
+            let mut pv = vec![];
+            for (index, &m) in moves.iter().enumerate().skip(first) {
+                let score = match play(board, m) {
+                    Some(child) => -self.negamax(&child, depth - 1, -INFINITY, -alpha, 1, &mut child_pv),
+                    None => {
+                        child_pv.clear();
+                        MATE - 1
+                    }
+                };
+                if self.aborted {
+                    return lines;
+                }
+                if score > alpha {
+                    alpha = score;
+                    best_index = index;
+                    pv.clear();
+                    pv.push(m);
+                    pv.extend_from_slice(&child_pv);
+                }
+            }
+            moves[first..=best_index].rotate_right(1);
+            lines.push(PvLine { score: alpha, pv });
+        }
+        self.tt.store(board.hash(), depth, Bound::Exact, lines[0].score, Some(moves[0]), 0);
+        lines
+    }
+
+    fn negamax(&mut self, board: &Board, depth: u32, mut alpha: i32, beta: i32, ply: u32, pv: &mut Vec<Move>) -> i32 {
+        pv.clear();
+        self.count_node();
+        if depth == 0 || ply >= MAX_PLY {
+            return self.quiesce(board, alpha, beta, ply);
+        }
+        if self.should_stop() {
+            return 0;
+        }
+        if let Some(probe) = self.tablebase.and_then(|tb| tb.probe(board)) {
+            return probe.score(ply);
+        }
+        let key = board.hash();
+        let mut tt_move = None;
+        if let Some(entry) = self.tt.probe(key, ply) {
+            tt_move = entry.best_move;
+            if entry.depth as u32 >= depth {
+                let cutoff = match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => entry.score >= beta,
+                    Bound::Upper => entry.score <= alpha
+                };
+                if cutoff {
+                    if let Some(m) = entry.best_move.filter(|m| logic::is_legal(board, *m)) {
+                        pv.push(m);
+                    }
+                    return entry.score;
+                }
+            }
+        }
+        let mut moves = logic::legal_moves(board);
+        if moves.is_empty() {
+            return 0;
+        }
+        order_moves(board, &mut moves);
+        if let Some(index) = tt_move.and_then(|tt_move| moves.iter().position(|m| *m == tt_move)) {
+            let m = moves.remove(index);
+            moves.insert(0, m);
+        }
+        let original_alpha = alpha;
+        let mut best_move = None;
+        let mut child_pv = vec![];
+        for m in moves {
+            let score = match play(board, m) {
+                Some(child) => -self.negamax(&child, depth - 1, -beta, -alpha, ply + 1, &mut child_pv),
+                None => {
+                    child_pv.clear();
+                    MATE - (ply + 1) as i32
+                }
+            };
+            if self.aborted {
+                return 0;
+            }
+            if score > alpha {
+                alpha = score;
+                best_move = Some(m);
+                pv.clear();
+                pv.push(m);
+                pv.extend_from_slice(&child_pv);
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        let bound = if alpha >= beta {
+            Bound::Lower
+        } else if alpha > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(key, depth, bound, alpha, best_move, ply);
+        alpha
+    }
+
+    // captures only, skipping those that lose material by exchange
+    fn quiesce(&mut self, board: &Board, mut alpha: i32, beta: i32, ply: u32) -> i32 {
+        let stand_pat = eval::evaluate(board, self.weights);
+        if stand_pat >= beta || ply >= MAX_PLY {
+            return stand_pat;
+        }
+        if self.should_stop() {
+            return 0;
+        }
+        alpha = alpha.max(stand_pat);
+        for m in quiescence_moves(board) {
+            self.count_node();
+            let score = match play(board, m) {
+                Some(child) => -self.quiesce(&child, -beta, -alpha, ply + 1),
+                None => MATE - (ply + 1) as i32
+            };
+            if self.aborted {
+                return 0;
+            }
+            if score > alpha {
+                alpha = score;
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        alpha
+    }
+}
+
+// None when the move takes the king and ends the game
+fn play(board: &Board, m: Move) -> Option<Board> {
+    if let Some((_, Piece::King)) = board.hexes[m.to] {
+        return None;
+    }
+    let mut child = board.clone();
+    logic::make_move(&mut child, m);
+    Some(child)
+}
+
+// the captures quiescence looks at, leaving out those that lose material
+// by exchange
+fn quiescence_moves(board: &Board) -> Vec<Move> {
+    let mut captures = logic::legal_moves(board);
+    captures.retain(|m| board.hexes[m.to].is_some() && see::see(board, *m) >= 0);
+    order_moves(board, &mut captures);
+    captures
+}
+
+// captures first, most valuable victim then least valuable attacker
+pub fn order_moves(board: &Board, moves: &mut [Move]) {
+    moves.sort_by_key(|m| {
+        let victim = board.hexes[m.to].map_or(0, |(_, piece)| eval::piece_value(piece));
+        let attacker = board.hexes[m.from].map_or(0, |(_, piece)| eval::piece_value(piece));
+        (-victim, if victim > 0 { attacker } else { 0 })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use super::*;
+    use crate::board::{Hexes, Player, HEX_COUNT};
+
+    fn engine(threads: usize) -> Engine {
+        Engine::with_options(EvalWeights::default(), EngineOptions { hash_mb: 4, threads, ..Default::default() })
+    }
+
+    fn depth_limit(depth: u32) -> Limits {
+        Limits {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn single_thread_search_is_deterministic() {
+        let board = Board::new();
+        let stop = AtomicBool::new(false);
+        let first = engine(1).search(&board, depth_limit(4), &stop, |_| {}).unwrap();
+        let second = engine(1).search(&board, depth_limit(4), &stop, |_| {}).unwrap();
+        assert_eq!(first.score, second.score);
+        assert_eq!(first.nodes, second.nodes);
+        assert_eq!(first.pv, second.pv);
+    }
+
+    #[test]
+    fn multi_pv_lines_are_distinct_and_best_first() {
+        let board = Board::new();
+        let stop = AtomicBool::new(false);
+        let single = engine(1).search(&board, depth_limit(3), &stop, |_| {}).unwrap();
+        let mut multi = engine(1);
+        multi.set_multi_pv(3);
+        let multi = multi.search(&board, depth_limit(3), &stop, |_| {}).unwrap();
+        assert_eq!(multi.lines.len(), 3);
+        assert_eq!(multi.score, single.score);
+        assert_eq!(multi.best_move(), single.best_move());
+        assert!(multi.lines.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        let firsts: std::collections::HashSet<Move> = multi.lines.iter().map(|line| line.pv[0]).collect();
+        assert_eq!(firsts.len(), 3);
+    }
+
+    #[test]
+    fn threads_find_the_same_mate() {
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        hexes[3] = Some((Player::Black, Piece::King));
+        hexes[20] = Some((Player::White, Piece::Queen));
+        hexes[87] = Some((Player::White, Piece::King));
+        hexes[90] = Some((Player::Black, Piece::Pawn));
+        let board = Board::from_hexes(hexes, Player::White);
+        let stop = AtomicBool::new(false);
+        let single = engine(1).search(&board, depth_limit(4), &stop, |_| {}).unwrap();
+        let threaded = engine(4).search(&board, depth_limit(4), &stop, |_| {}).unwrap();
+        assert!(is_mate_score(single.score));
+        assert_eq!(single.score, threaded.score);
+        assert!(logic::is_legal(&board, threaded.best_move().unwrap()));
+    }
+
+    #[test]
+    fn quiescence_skips_losing_captures() {
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        // the queen can take a pawn the knight defends, or the knight
+        hexes[45] = Some((Player::Black, Piece::Pawn));
+        hexes[46] = Some((Player::White, Piece::Queen));
+        hexes[47] = Some((Player::Black, Piece::Knight));
+        hexes[87] = Some((Player::White, Piece::King));
+        hexes[3] = Some((Player::Black, Piece::King));
+        let board = Board::from_hexes(hexes, Player::White);
+        assert!(logic::is_legal(&board, Move { from: 46, to: 45 }));
+        assert_eq!(quiescence_moves(&board), vec![Move { from: 46, to: 47 }]);
+    }
+
+    #[test]
+    fn takes_the_king_when_it_can() {
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        hexes[45] = Some((Player::Black, Piece::King));
+        hexes[46] = Some((Player::White, Piece::Queen));
+        hexes[87] = Some((Player::White, Piece::King));
+        hexes[3] = Some((Player::Black, Piece::Queen));
+        let board = Board::from_hexes(hexes, Player::White);
+        let stop = AtomicBool::new(false);
+        let info = engine(1).search(&board, depth_limit(2), &stop, |_| {}).unwrap();
+        assert_eq!(info.best_move(), Some(Move { from: 46, to: 45 }));
+        assert!(is_mate_score(info.score) && info.score > 0);
+    }
+
+    #[test]
+    fn all_threads_honour_stop() {
+        let board = Board::new();
+        let stop = AtomicBool::new(true);
+        let start = Instant::now();
+        let info = engine(4).search(&board, Limits::default(), &stop, |_| {}).unwrap();
+        // only the first iteration, which is never interrupted
+        assert_eq!(info.depth, 1);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::board::*;
+use crate::eval::piece_value;
+use crate::logic::{self, Move};
+use crate::notation;
+use crate::pieces::Piece;
+
+// Static exchange evaluation of a capture sequence on m.to, best play for
+// both sides, from the point of view of the side making m. A fortress
+// standing on the target hex can only be taken while at least
+// FORTRESS_ARMOUR pieces of the capturing side bear on it, so a piece that
+// never gets to capture still counts towards unlocking it.
+pub fn see(board: &Board, m: Move) -> i32 {
+    let mut hexes = board.hexes;
+    let (mut side, mut occupant) = match hexes[m.from] {
+        Some(moving) => moving,
+        None => return 0
+    };
+    let mut gains = vec![hexes[m.to].map_or(0, |(_, piece)| piece_value(piece))];
+    hexes[m.to] = hexes[m.from];
+    hexes[m.from] = None;
+    loop {
+        side = side.opposite();
+        let attackers = logic::attackers(hexes, m.to, side);
+        if occupant == Piece::Fortress && (attackers.len() as u8) < logic::FORTRESS_ARMOUR {
+            break;
+        }
+        let capturer = attackers.iter().cloned().min_by_key(|&h| {
+            hexes[h].map_or(0, |(_, piece)| piece_value(piece))
+        });
+        let capturer = match capturer {
+            Some(capturer) => capturer,
+            None => break
+        };
+        let last = *gains.last().unwrap();
+        gains.push(piece_value(occupant) - last);
+        if occupant == Piece::King {
+            break;
+        }
+        occupant = hexes[capturer].map(|(_, piece)| piece).unwrap();
+        hexes[m.to] = hexes[capturer];
+        hexes[capturer] = None;
+    }
+    // either side may stop capturing whenever carrying on would lose
+    while gains.len() > 1 {
+        let last = gains.pop().unwrap();
+        let previous = gains.last_mut().unwrap();
+        *previous = -(-*previous).max(last);
+    }
+    gains[0]
+}
+
+// player's pieces the other side could win material by taking, with the
+// most each would win; the other side is to move on board
+pub fn hanging(board: &Board, player: Player) -> Vec<(usize, i32)> {
+    let mut out: Vec<(usize, i32)> = vec![];
+    for m in logic::legal_moves(board) {
+        if !has_colored_piece(board.hexes, m.to, player) {
+            continue;
+        }
+        let gain = see(board, m);
+        if gain <= 0 {
+            continue;
+        }
+        match out.iter_mut().find(|(hex, _)| *hex == m.to) {
+            Some(entry) => entry.1 = entry.1.max(gain),
+            None => out.push((m.to, gain))
+        }
+    }
+    out.sort_by_key(|(_, gain)| -gain);
+    out
+}
+
+// what a move gives away, said in words and with the hexes to mark
+#[derive(Clone, Debug)]
+pub struct Warning {
+    pub text: String,
+    pub hexes: Vec<usize>
+}
+
+// the move's own losing exchange and the pieces it leaves to be taken
+pub fn blunder_warning(board: &Board, m: Move) -> Option<Warning> {
+    let player = board.current_turn;
+    let mut after = board.clone();
+    logic::make_move(&mut after, m);
+    if logic::winner(&after).is_some() {
+        return None;
+    }
+    let mut problems = vec![];
+    if board.hexes[m.to].is_some() && see(board, m) < 0 {
+        problems.push("the exchange loses material".to_string());
+    }
+    let mut hexes = vec![];
+    for (hex, _) in hanging(&after, player) {
+        if let Some((_, piece)) = after.hexes[hex] {
+            problems.push(match piece {
+                Piece::Fortress => format!("the Fortress on {} loses its armour", notation::hex_name(hex)),
+                _ => format!("the {} on {} can be taken", piece, notation::hex_name(hex))
+            });
+            hexes.push(hex);
+        }
+    }
+    if problems.is_empty() {
+        return None;
+    }
+    Some(Warning {
+        text: format!("{}: {}.", notation::describe_move(board, m), problems.join(", ")),
+        hexes
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the middle of the board, with room on both sides
+    const TARGET: usize = 45;
+
+    fn board_with(pieces: &[(usize, Player, Piece)]) -> Board {
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        hexes[87] = Some((Player::White, Piece::King));
+        hexes[3] = Some((Player::Black, Piece::King));
+        for &(hex, player, piece) in pieces {
+            hexes[hex] = Some((player, piece));
+        }
+        Board::from_hexes(hexes, Player::White)
+    }
+
+    #[test]
+    fn a_fortress_with_one_attacker_cannot_be_taken_back() {
+        let board = board_with(&[
+            (TARGET, Player::Black, Piece::General),
+            (TARGET - 1, Player::White, Piece::Fortress),
+            (TARGET + 2, Player::Black, Piece::Knight)
+        ]);
+        assert_eq!(see(&board, Move { from: TARGET - 1, to: TARGET }), piece_value(Piece::General));
+    }
+
+    #[test]
+    fn a_second_attacker_unlocks_the_fortress() {
+        let board = board_with(&[
+            (TARGET, Player::Black, Piece::General),
+            (TARGET - 1, Player::White, Piece::Fortress),
+            (TARGET + 2, Player::Black, Piece::Knight),
+            (TARGET - 2, Player::Black, Piece::Knight)
+        ]);
+        let expected = piece_value(Piece::General) - piece_value(Piece::Fortress);
+        assert_eq!(see(&board, Move { from: TARGET - 1, to: TARGET }), expected);
+    }
+
+    #[test]
+    fn taking_a_defended_pawn_with_the_queen_loses() {
+        let board = board_with(&[
+            (TARGET, Player::Black, Piece::Pawn),
+            (TARGET + 1, Player::White, Piece::Queen),
+            (TARGET + 2, Player::Black, Piece::Knight)
+        ]);
+        let expected = piece_value(Piece::Pawn) - piece_value(Piece::Queen);
+        assert_eq!(see(&board, Move { from: TARGET + 1, to: TARGET }), expected);
+    }
+
+    #[test]
+    fn uncovering_a_fortress_warns_about_it() {
+        // the knight on TARGET + 1 is all that keeps the queen off the fortress
+        let board = board_with(&[
+            (TARGET, Player::White, Piece::Fortress),
+            (TARGET + 1, Player::White, Piece::Knight),
+            (TARGET + 2, Player::Black, Piece::Queen),
+            (TARGET - 2, Player::Black, Piece::Knight)
+        ]);
+        assert!(!logic::is_vulnerable(&board, TARGET));
+        let m = logic::legal_moves(&board).into_iter().find(|m| m.from == TARGET + 1).unwrap();
+        let warning = blunder_warning(&board, m).unwrap();
+        let fortress = format!("the Fortress on {} loses its armour", notation::hex_name(TARGET));
+        assert!(warning.text.contains(&fortress), "{}", warning.text);
+        assert!(warning.hexes.contains(&TARGET));
+    }
+
+    #[test]
+    fn a_losing_capture_warns() {
+        let board = board_with(&[
+            (TARGET, Player::Black, Piece::Pawn),
+            (TARGET + 1, Player::White, Piece::Queen),
+            (TARGET + 2, Player::Black, Piece::Knight)
+        ]);
+        let warning = blunder_warning(&board, Move { from: TARGET + 1, to: TARGET }).unwrap();
+        assert!(warning.text.contains("the exchange loses material"), "{}", warning.text);
+    }
+
+    #[test]
+    fn taking_the_king_never_warns() {
+        let mut board = board_with(&[
+            (TARGET + 1, Player::White, Piece::Queen),
+            (TARGET + 2, Player::Black, Piece::Knight)
+        ]);
+        board.hexes[3] = None;
+        board.hexes[TARGET] = Some((Player::Black, Piece::King));
+        let board = Board::from_hexes(board.hexes, Player::White);
+        assert!(blunder_warning(&board, Move { from: TARGET + 1, to: TARGET }).is_none());
+    }
+
+    #[test]
+    fn a_safe_move_does_not_warn() {
+        let board = board_with(&[(TARGET + 20, Player::White, Piece::Pawn)]);
+        let m = logic::legal_moves(&board).into_iter().find(|m| m.from == TARGET + 20).unwrap();
+        assert!(blunder_warning(&board, m).is_none());
+    }
+}
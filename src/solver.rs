@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+use crate::board::{Board, Player, HEX_COUNT};
+use crate::logic::{self, Move};
+use crate::notation;
+use crate::pieces::Piece;
+
+// Forced wins for the side to move. A win is taking the enemy king, so
+// "within n moves" means the king falls on one of the attacker's next n
+// moves whatever the defender does; a checkmate in n-1 is a king capture
+// in n. A defender with no moves at all has drawn, not lost.
+//
+// The search is exhaustive, so an empty answer really means there is no
+// forced win, but the attacker's checks and captures are tried before
+// quiet moves and the defender's escapes before anything else, which
+// finds most wins and refutations early.
+
+#[derive(Clone, Debug)]
+pub struct Variation {
+    // the attacker's move
+    pub m: Move,
+    // every defender reply and how the attack goes on, empty once m takes the king
+    pub replies: Vec<(Move, Variation)>
+}
+
+#[derive(Clone, Debug)]
+pub struct Solution {
+    // the attacker's moves up to and including the king capture
+    pub moves: u32,
+    pub tree: Variation
+}
+
+struct Solver {
+    known: HashMap<(u64, u32), bool>
+}
+
+fn king_hex(board: &Board, player: Player) -> Option<usize> {
+    (0..HEX_COUNT).find(|h| board.hexes[*h] == Some((player, Piece::King)))
+}
+
+// whether the side to move can take the enemy king right now
+fn can_take_king(board: &Board) -> bool {
+    let player = board.current_turn;
+    match king_hex(board, player.opposite()) {
+        Some(king) => (0..HEX_COUNT).any(|h| {
+            matches!(board.hexes[h], Some((colour, _)) if colour == player)
+                && logic::find_valid_moves(board, h).contains(&king)
+        }),
+        None => false
+    }
+}
+
+fn takes_king(board: &Board, m: Move) -> bool {
+    matches!(board.hexes[m.to], Some((_, Piece::King)))
+}
+
+fn play(board: &Board, m: Move) -> Board {
+    let mut child = board.clone();
+    logic::make_move(&mut child, m);
+    child
+}
+
+impl Solver {
+    fn new() -> Solver {
+        Solver {
+            known: HashMap::new()
+        }
+    }
+
+    // king captures, then checks, then other captures, then the rest
+    fn attacking_moves(&self, board: &Board) -> Vec<(Move, Board)> {
+        let mut moves: Vec<(u8, Move, Board)> = logic::legal_moves(board).into_iter()
+            .map(|m| {
+                let child = play(board, m);
+                let order = if takes_king(board, m) {
+                    0
+                } else if can_take_king(&Board::from_hexes(child.hexes, board.current_turn)) {
+                    1
+                } else if board.hexes[m.to].is_some() {
+                    2
+                } else {
+                    3
+                };
+                (order, m, child)
+            })
+            .collect();
+        moves.sort_by_key(|(order, _, _)| *order);
+        moves.into_iter().map(|(_, m, child)| (m, child)).collect()
+    }
+
+    // king moves and captures are the likeliest escapes
+    fn defending_moves(&self, board: &Board) -> Vec<Move> {
+        let mut moves = logic::legal_moves(board);
+        moves.sort_by_key(|m| {
+            if takes_king(board, *m) {
+                0
+            } else if matches!(board.hexes[m.from], Some((_, Piece::King))) {
+                1
+            } else if board.hexes[m.to].is_some() {
+                2
+            } else {
+                3
+            }
+        });
+        moves
+    }
+
+    // the side to move takes the king within moves_left of its moves
+    fn attacker_wins(&mut self, board: &Board, moves_left: u32) -> bool {
+        if moves_left == 0 {
+            return false;
+        }
+        if can_take_king(board) {
+            return true;
+        }
+        if moves_left == 1 {
+            return false;
+        }
+        let key = (board.hash(), moves_left);
+        if let Some(known) = self.known.get(&key) {
+            return *known;
+        }
+        let wins = self.attacking_moves(board).into_iter()
+            .any(|(_, child)| self.defender_loses(&child, moves_left - 1));
+        self.known.insert(key, wins);
+        wins
+    }
+
+    // every reply of the side to move lets the attacker win in time
+    fn defender_loses(&mut self, board: &Board, moves_left: u32) -> bool {
+        let replies = self.defending_moves(board);
+        if replies.is_empty() {
+            return false;
+        }
+        for reply in replies {
+            if takes_king(board, reply) {
+                return false;
+            }
+            let child = play(board, reply);
+            let lost = if moves_left == 1 {
+                can_take_king(&child)
+            } else {
+                self.attacker_wins(&child, moves_left)
+            };
+            if !lost {
+                return false;
+            }
+        }
+        true
+    }
+
+    // the fewest moves m needs to win with, at most moves_left
+    fn moves_to_win(&mut self, board: &Board, m: Move, moves_left: u32) -> Option<u32> {
+        if takes_king(board, m) {
+            return Some(1);
+        }
+        let child = play(board, m);
+        (2..=moves_left).find(|n| self.defender_loses(&child, n - 1))
+    }
+
+    // m is known to win within moves_left
+    fn tree(&mut self, board: &Board, m: Move, moves_left: u32) -> Variation {
+        if takes_king(board, m) {
+            return Variation { m, replies: vec![] };
+        }
+        let child = play(board, m);
+        let replies = self.defending_moves(&child).into_iter()
+            .map(|reply| {
+                let after = play(&child, reply);
+                let (next, left) = self.quickest_win(&after, moves_left - 1)
+                    .expect("a lost defence has a winning answer to every reply");
+                (reply, self.tree(&after, next, left))
+            })
+            .collect();
+        Variation { m, replies }
+    }
+
+    fn quickest_win(&mut self, board: &Board, moves_left: u32) -> Option<(Move, u32)> {
+        let moves = logic::legal_moves(board);
+        (1..=moves_left).find_map(|n| {
+            moves.iter()
+                .find(|m| self.moves_to_win(board, **m, n) == Some(n))
+                .map(|m| (*m, n))
+        })
+    }
+}
+
+impl Variation {
+    // the attacker's moves still needed, counting this one
+    pub fn moves(&self) -> u32 {
+        1 + self.replies.iter().map(|(_, next)| next.moves()).max().unwrap_or(0)
+    }
+
+    // the attacker's moves against the defence that holds out longest
+    pub fn main_line(&self) -> Vec<Move> {
+        let mut line = vec![self.m];
+        if let Some((reply, next)) = self.replies.iter().max_by_key(|(_, next)| next.moves()) {
+            line.push(*reply);
+            line.extend(next.main_line());
+        }
+        line
+    }
+}
+
+// every first move that forces the king's capture within max_moves,
+// quickest first
+pub fn solve(board: &Board, max_moves: u32) -> Vec<Solution> {
+    let mut solver = Solver::new();
+    let mut solutions = vec![];
+    for m in logic::legal_moves(board) {
+        if let Some(moves) = solver.moves_to_win(board, m, max_moves) {
+            let tree = solver.tree(board, m, moves);
+            solutions.push(Solution { moves, tree });
+        }
+    }
+    solutions.sort_by_key(|s| s.moves);
+    solutions
+}
+
+// whether m still takes the king within moves_left
+pub fn wins_within(board: &Board, m: Move, moves_left: u32) -> bool {
+    Solver::new().moves_to_win(board, m, moves_left).is_some()
+}
+
+// the reply that puts off the king's capture longest, or one that
+// escapes it altogether
+pub fn longest_defence(board: &Board, moves_left: u32) -> Option<Move> {
+    let mut solver = Solver::new();
+    let mut best: Option<(Move, u32)> = None;
+    for reply in solver.defending_moves(board) {
+        if takes_king(board, reply) {
+            return Some(reply);
+        }
+        let after = play(board, reply);
+        match solver.quickest_win(&after, moves_left) {
+            None => return Some(reply),
+            Some((_, n)) if best.is_none_or(|(_, longest)| n > longest) => best = Some((reply, n)),
+            _ => {}
+        }
+    }
+    best.map(|(reply, _)| reply)
+}
+
+fn move_number(ply: u32, player: Player, first: Player) -> String {
+    let number = ply / 2 + 1;
+    if player == first {
+        format!("{}.", number)
+    } else {
+        format!("{}...", number)
+    }
+}
+
+fn write_variation(out: &mut String, board: &Board, variation: &Variation, ply: u32, first: Player) {
+    let indent = "  ".repeat(ply as usize);
+    let attacker = board.current_turn;
+    out.push_str(&format!("{}{} {}\n", indent, move_number(ply, attacker, first), notation::describe_move(board, variation.m)));
+    if variation.replies.is_empty() {
+        return;
+    }
+    let child = play(board, variation.m);
+    for (reply, next) in &variation.replies {
+        out.push_str(&format!("{}  {} {}\n", indent, move_number(ply + 1, child.current_turn, first), notation::describe_move(&child, *reply)));
+        write_variation(out, &play(&child, *reply), next, ply + 2, first);
+    }
+}
+
+pub fn solution_text(board: &Board, solution: &Solution) -> String {
+    let mut out = String::new();
+    write_variation(&mut out, board, &solution.tree, 0, board.current_turn);
+    out
+}
+
+pub const USAGE: &str = "usage: hexchess solve \"<position>\" [--moves N] [--tree]";
+
+pub fn main(args: &[String]) -> Result<(), String> {
+    let mut position = None;
+    let mut max_moves = 3;
+    let mut print_tree = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--moves" => {
+                max_moves = args.next().and_then(|n| n.parse().ok()).ok_or("--moves needs a number")?;
+            },
+            "--tree" => print_tree = true,
+            _ if position.is_none() => position = Some(arg.clone()),
+            _ => return Err(USAGE.to_string())
+        }
+    }
+    let position = position.ok_or(USAGE)?;
+    let board = if position == "startpos" { Board::new() } else { notation::parse_position(&position)? };
+    let solutions = solve(&board, max_moves);
+    if solutions.is_empty() {
+        println!("no forced win for {:?} within {} moves", board.current_turn, max_moves);
+        return Ok(());
+    }
+    for solution in &solutions {
+        println!("{} takes the king in {}", notation::describe_move(&board, solution.tree.m), solution.moves);
+        if print_tree {
+            print!("{}", solution_text(&board, solution));
+        }
+    }
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Hexes;
+
+    // the white king cornered on a11 by a black king and fortress, black to move
+    fn cornered(black_king: usize, fortress: usize) -> Board {
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        hexes[0] = Some((Player::White, Piece::King));
+        hexes[black_king] = Some((Player::Black, Piece::King));
+        hexes[fortress] = Some((Player::Black, Piece::Fortress));
+        Board::from_hexes(hexes, Player::Black)
+    }
+
+    // the tree answers every reply and each line ends by taking the king
+    fn is_proof(board: &Board, variation: &Variation) -> bool {
+        if variation.replies.is_empty() {
+            return takes_king(board, variation.m);
+        }
+        let child = play(board, variation.m);
+        let mut replies: Vec<Move> = variation.replies.iter().map(|(reply, _)| *reply).collect();
+        let mut legal = logic::legal_moves(&child);
+        replies.sort_by_key(|m| (m.from, m.to));
+        legal.sort_by_key(|m| (m.from, m.to));
+        replies == legal && variation.replies.iter().all(|(reply, next)| is_proof(&play(&child, *reply), next))
+    }
+
+    #[test]
+    fn finds_a_mate_in_one() {
+        let board = cornered(2, 9);
+        assert!(solve(&board, 1).is_empty());
+        let solutions = solve(&board, 2);
+        assert_eq!(solutions.len(), 1);
+        assert_eq!(solutions[0].moves, 2);
+        assert!(is_proof(&board, &solutions[0].tree));
+        assert_eq!(solution_text(&board, &solutions[0]), "\
+1. Fd10-b10
+  1... Ka11-b11
+    2. Kc11xb11
+  1... Ka11-a10
+    2. Fb10xa10
+");
+        let m = solutions[0].tree.m;
+        assert!(wins_within(&board, m, 2));
+        assert!(!wins_within(&board, m, 1));
+    }
+
+    #[test]
+    fn finds_a_mate_in_two() {
+        let board = cornered(3, 21);
+        assert!(solve(&board, 2).is_empty());
+        let solutions = solve(&board, 3);
+        let firsts: Vec<String> = solutions.iter().map(|s| notation::describe_move(&board, s.tree.m)).collect();
+        assert_eq!(firsts, ["Fa8-a10", "Fa8-b9"]);
+        for solution in &solutions {
+            assert_eq!(solution.moves, 3);
+            assert_eq!(solution.tree.moves(), 3);
+            assert!(is_proof(&board, &solution.tree));
+        }
+        let line: Vec<String> = {
+            let mut position = board.clone();
+            solutions[0].tree.main_line().into_iter()
+                .map(|m| {
+                    let text = notation::describe_move(&position, m);
+                    logic::make_move(&mut position, m);
+                    text
+                })
+                .collect()
+        };
+        // the last two plies depend on which of the equally long defences comes first
+        assert_eq!(line.len(), 5);
+        assert_eq!(line[..3], ["Fa8-a10", "Ka11-b11", "Kd11-d10"]);
+        // b11 holds out a move longer than b10
+        let after = play(&board, solutions[0].tree.m);
+        assert_eq!(notation::describe_move(&after, longest_defence(&after, 2).unwrap()), "Ka11-b11");
+    }
+
+    #[test]
+    fn finds_nothing_without_a_forced_win() {
+        assert!(solve(&Board::new(), 2).is_empty());
+        // a lone king cannot take another that keeps its distance
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        hexes[0] = Some((Player::White, Piece::King));
+        hexes[45] = Some((Player::Black, Piece::King));
+        let bare_kings = Board::from_hexes(hexes, Player::Black);
+        assert!(solve(&bare_kings, 4).is_empty());
+        assert!(logic::legal_moves(&bare_kings).iter().all(|m| !wins_within(&bare_kings, *m, 4)));
+    }
+}
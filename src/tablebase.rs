@@ -0,0 +1,829 @@
+use std::collections::HashMap;
+use std::path::Path;
+use crate::board::{self, Board, Hexes, Player, HEX_COUNT};
+use crate::logic::{self, Move};
+use crate::pieces::{Piece, ALL_PIECES};
+use crate::search::MATE;
+
+// Endgame tables, one file per material such as KFvK (white pieces, v,
+// black pieces). A table holds a value for every placement of its pieces
+// with either side to move: the number of plies until the side to move
+// takes the enemy king when it wins, until its own king is taken when it
+// loses, or 0 for a draw. Odd values are wins and even values losses.
+//
+// Tables are built by retrograde analysis. Every position is first
+// scored by the moves that leave the table (captures, promotions, taking
+// the king), then results are pushed back through unmoves one distance at
+// a time. Moves come from the rules in logic, so the fortress armour
+// rule is exactly the one played.
+//
+// The white king only ever stands on the left half of the board, a
+// position with it on the right is looked up as its mirror image. Black
+// pawns queen on hexes that are not mirror images of each other, so
+// tables with black pawns are stored whole.
+//
+// A table has an entry for every placement whether it is possible or not,
+// so each piece multiplies its size by 91. Tables over MAX_ENTRIES are
+// refused before anything is allocated, which in practice stops at four
+// pieces.
+
+const MAGIC: &[u8; 4] = b"HXTB";
+const VERSION: u8 = 1;
+const EXTENSION: &str = "hxtb";
+const RESOLVED: u16 = 0x8000;
+const CANNOT_LOSE: u8 = u8::MAX;
+// generating takes three bytes an entry
+pub const MAX_ENTRIES: usize = 1 << 28;
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Material {
+    white: Vec<Piece>,
+    black: Vec<Piece>
+}
+
+fn piece_order(piece: Piece) -> usize {
+    ALL_PIECES.iter().position(|p| *p == piece).unwrap()
+}
+
+impl Material {
+    fn new(mut white: Vec<Piece>, mut black: Vec<Piece>) -> Material {
+        white.sort_by_key(|p| piece_order(*p));
+        black.sort_by_key(|p| piece_order(*p));
+        Material { white, black }
+    }
+
+    // "KGvKP"
+    pub fn parse(text: &str) -> Result<Material, String> {
+        let (white, black) = text.split_once('v').ok_or_else(|| format!("expected white v black: {}", text))?;
+        let pieces = |side: &str| side.chars()
+            .map(|c| ALL_PIECES.iter().find(|p| p.code() == c).cloned().ok_or_else(|| format!("unknown piece: {}", c)))
+            .collect::<Result<Vec<Piece>, String>>();
+        let material = Material::new(pieces(white)?, pieces(black)?);
+        for side in [&material.white, &material.black] {
+            if side.iter().filter(|p| **p == Piece::King).count() != 1 {
+                return Err(format!("{}: each side needs exactly one king", text));
+            }
+        }
+        Ok(material)
+    }
+
+    pub fn of(hexes: &Hexes) -> Option<Material> {
+        let mut white = vec![];
+        let mut black = vec![];
+        for (player, piece) in hexes.iter().flatten() {
+            match player {
+                Player::White => white.push(*piece),
+                Player::Black => black.push(*piece)
+            }
+        }
+        let material = Material::new(white, black);
+        if material.white.first() == Some(&Piece::King) && material.black.first() == Some(&Piece::King)
+            && material.white.iter().chain(material.black.iter()).filter(|p| **p == Piece::King).count() == 2 {
+            Some(material)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(&self) -> String {
+        let codes = |side: &[Piece]| side.iter().map(|p| p.code()).collect::<String>();
+        format!("{}v{}", codes(&self.white), codes(&self.black))
+    }
+
+    pub fn piece_count(&self) -> usize {
+        self.white.len() + self.black.len()
+    }
+
+    // black pawns break the left-right symmetry
+    fn is_symmetric(&self) -> bool {
+        !self.black.contains(&Piece::Pawn)
+    }
+
+    // entries in its table, or why it is too big to build
+    pub fn table_size(&self) -> Result<usize, String> {
+        let king_hexes = (0..HEX_COUNT).filter(|h| !self.is_symmetric() || is_left_half(*h)).count();
+        (1..self.piece_count())
+            .try_fold(king_hexes * 2, |size, _| size.checked_mul(HEX_COUNT))
+            .filter(|size| *size <= MAX_ENTRIES)
+            .ok_or_else(|| format!("{}: too many pieces, tables hold at most {} entries", self.name(), MAX_ENTRIES))
+    }
+
+    // kings first, then white's pieces and black's
+    fn slots(&self) -> Vec<(Player, Piece)> {
+        let mut slots = vec![(Player::White, Piece::King), (Player::Black, Piece::King)];
+        slots.extend(self.white[1..].iter().map(|p| (Player::White, *p)));
+        slots.extend(self.black[1..].iter().map(|p| (Player::Black, *p)));
+        slots
+    }
+
+    fn side(&self, player: Player) -> &[Piece] {
+        match player {
+            Player::White => &self.white,
+            Player::Black => &self.black
+        }
+    }
+
+    fn with(&self, player: Player, pieces: Vec<Piece>) -> Material {
+        match player {
+            Player::White => Material::new(pieces, self.black.clone()),
+            Player::Black => Material::new(self.white.clone(), pieces)
+        }
+    }
+
+    // materials one move can lead to: a capture, a promotion or both
+    pub fn successors(&self) -> Vec<Material> {
+        let mut out = vec![];
+        let remove = |pieces: &[Piece], piece: Piece| {
+            let mut pieces = pieces.to_vec();
+            let index = pieces.iter().position(|p| *p == piece).unwrap();
+            pieces.remove(index);
+            pieces
+        };
+        for mover in [Player::White, Player::Black] {
+            let own = self.side(mover).to_vec();
+            let enemy = self.side(mover.opposite()).to_vec();
+            let mut own_options = vec![own.clone()];
+            if own.contains(&Piece::Pawn) {
+                let mut promoted = remove(&own, Piece::Pawn);
+                promoted.push(Piece::Queen);
+                own_options.push(promoted);
+            }
+            let mut enemy_options = vec![enemy.clone()];
+            for piece in enemy.iter().skip(1) {
+                enemy_options.push(remove(&enemy, *piece));
+            }
+            for own_pieces in &own_options {
+                for enemy_pieces in &enemy_options {
+                    let material = self.with(mover, own_pieces.clone()).with(mover.opposite(), enemy_pieces.clone());
+                    if material != *self && !out.contains(&material) {
+                        out.push(material);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Wdl {
+    Win,
+    Draw,
+    Loss
+}
+
+// from the point of view of the side to move
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Probe {
+    pub wdl: Wdl,
+    // until a king is taken, 0 for draws
+    pub plies: u16
+}
+
+impl Probe {
+    fn from_value(value: u16) -> Probe {
+        let wdl = if value == 0 {
+            Wdl::Draw
+        } else if !value.is_multiple_of(2) {
+            Wdl::Win
+        } else {
+            Wdl::Loss
+        };
+        Probe { wdl, plies: value }
+    }
+
+    // the same result seen from the side that moved into the position
+    fn before(self) -> Probe {
+        match self.wdl {
+            Wdl::Win => Probe { wdl: Wdl::Loss, plies: self.plies + 1 },
+            Wdl::Loss => Probe { wdl: Wdl::Win, plies: self.plies + 1 },
+            Wdl::Draw => self
+        }
+    }
+
+    // a search score at ply, mates counted like the search counts them
+    pub fn score(self, ply: u32) -> i32 {
+        let distance = (ply + self.plies as u32) as i32;
+        match self.wdl {
+            Wdl::Win => MATE - distance,
+            Wdl::Loss => -(MATE - distance),
+            Wdl::Draw => 0
+        }
+    }
+
+    pub fn describe(self, to_move: Player) -> String {
+        let moves = self.plies.div_ceil(2);
+        match self.wdl {
+            Wdl::Win => format!("{:?} wins in {}", to_move, moves),
+            Wdl::Loss => format!("{:?} wins in {}", to_move.opposite(), moves),
+            Wdl::Draw => "draw".to_string()
+        }
+    }
+
+    // quick wins first and slow losses last
+    fn rank(self) -> i32 {
+        match self.wdl {
+            Wdl::Win => 100_000 - self.plies as i32,
+            Wdl::Draw => 0,
+            Wdl::Loss => -100_000 + self.plies as i32
+        }
+    }
+}
+
+fn is_left_half(hex: usize) -> bool {
+    board::mirror_hex(hex) >= hex
+}
+
+pub struct Table {
+    material: Material,
+    slots: Vec<(Player, Piece)>,
+    symmetric: bool,
+    // hexes the white king is indexed over and the index of each hex
+    king_hexes: Vec<usize>,
+    king_index: Vec<Option<usize>>,
+    values: Vec<u16>
+}
+
+impl Table {
+    fn empty(material: &Material) -> Result<Table, String> {
+        let size = material.table_size()?;
+        let symmetric = material.is_symmetric();
+        let king_hexes: Vec<usize> = (0..HEX_COUNT).filter(|h| !symmetric || is_left_half(*h)).collect();
+        let mut king_index = vec![None; HEX_COUNT];
+        for (index, hex) in king_hexes.iter().enumerate() {
+            king_index[*hex] = Some(index);
+        }
+        Ok(Table {
+            material: material.clone(),
+            slots: material.slots(),
+            symmetric,
+            king_hexes,
+            king_index,
+            values: vec![0; size]
+        })
+    }
+
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // placement must have the white king on an indexed hex
+    fn raw_index(&self, placement: &[usize], turn: Player) -> usize {
+        let mut index = self.king_index[placement[0]].unwrap();
+        for hex in &placement[1..] {
+            index = index * HEX_COUNT + hex;
+        }
+        index * 2 + if turn == Player::White { 0 } else { 1 }
+    }
+
+    fn index(&self, placement: &[usize], turn: Player) -> usize {
+        if !self.symmetric {
+            return self.raw_index(placement, turn);
+        }
+        let king = placement[0];
+        let mirrored = || placement.iter().map(|h| board::mirror_hex(*h)).collect::<Vec<usize>>();
+        if board::mirror_hex(king) == king {
+            // on the middle file both images are indexed, use the smaller
+            self.raw_index(placement, turn).min(self.raw_index(&mirrored(), turn))
+        } else if is_left_half(king) {
+            self.raw_index(placement, turn)
+        } else {
+            self.raw_index(&mirrored(), turn)
+        }
+    }
+
+    // None for impossible placements and for the unused image of a mirrored pair
+    fn position(&self, index: usize) -> Option<(Vec<usize>, Player)> {
+        let turn = if index.is_multiple_of(2) { Player::White } else { Player::Black };
+        let mut rest = index / 2;
+        let mut placement = vec![0; self.slots.len()];
+        for slot in (1..self.slots.len()).rev() {
+            placement[slot] = rest % HEX_COUNT;
+            rest /= HEX_COUNT;
+        }
+        placement[0] = self.king_hexes[rest];
+        if !self.is_possible(&placement) || self.index(&placement, turn) != index {
+            return None;
+        }
+        Some((placement, turn))
+    }
+
+    fn is_possible(&self, placement: &[usize]) -> bool {
+        for (slot, hex) in placement.iter().enumerate() {
+            if placement[..slot].contains(hex) {
+                return false;
+            }
+            if let (player, Piece::Pawn) = self.slots[slot] {
+                if board::is_queening_hex(*hex, player) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn hexes(&self, placement: &[usize]) -> Hexes {
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        for (slot, hex) in placement.iter().enumerate() {
+            hexes[*hex] = Some(self.slots[slot]);
+        }
+        hexes
+    }
+
+    fn placement_of(&self, hexes: &Hexes) -> Option<Vec<usize>> {
+        let mut placement = vec![usize::MAX; self.slots.len()];
+        for (hex, contents) in hexes.iter().enumerate() {
+            if let Some(contents) = contents {
+                let slot = (0..self.slots.len()).find(|s| self.slots[*s] == *contents && placement[*s] == usize::MAX)?;
+                placement[slot] = hex;
+            }
+        }
+        if placement.contains(&usize::MAX) {
+            None
+        } else {
+            Some(placement)
+        }
+    }
+
+    pub fn probe(&self, board: &Board) -> Option<Probe> {
+        let placement = self.placement_of(&board.hexes)?;
+        Some(Probe::from_value(self.values[self.index(&placement, board.current_turn)]))
+    }
+
+    // positions reached by taking back a move of the side not to move,
+    // without uncapturing or unpromoting
+    fn unmoves(&self, placement: &[usize], turn: Player, reach: &Reach) -> Vec<usize> {
+        let mover = turn.opposite();
+        let mut out = vec![];
+        for (slot, &to) in placement.iter().enumerate() {
+            let (player, piece) = self.slots[slot];
+            if player != mover {
+                continue;
+            }
+            for &from in reach.origins(player, piece, to) {
+                if placement.contains(&from) {
+                    continue;
+                }
+                let mut before = placement.to_vec();
+                before[slot] = from;
+                if !self.is_possible(&before) {
+                    continue;
+                }
+                if logic::piece_moves(&self.hexes(&before), from).contains(&to) {
+                    out.push(self.index(&before, mover));
+                }
+            }
+        }
+        out.sort_unstable();
+        out.dedup();
+        out
+    }
+
+    fn generate(material: &Material, tablebase: &Tablebase) -> Result<Table, String> {
+        let mut table = Table::empty(material)?;
+        let reach = Reach::new(&table.slots);
+        let size = table.len();
+        // unresolved positions hold the longest loss through an exit
+        let mut values = std::mem::take(&mut table.values);
+        let mut remaining = vec![CANNOT_LOSE; size];
+        let mut buckets: Vec<Vec<usize>> = vec![];
+        let push = |buckets: &mut Vec<Vec<usize>>, plies: usize, index: usize| {
+            if buckets.len() <= plies {
+                buckets.resize(plies + 1, vec![]);
+            }
+            buckets[plies].push(index);
+        };
+        for index in 0..size {
+            let (placement, turn) = match table.position(index) {
+                Some(position) => position,
+                None => continue
+            };
+            let board = Board::from_hexes(table.hexes(&placement), turn);
+            let moves = logic::legal_moves(&board);
+            if moves.is_empty() {
+                // stuck, a draw
+                continue;
+            }
+            let mut children = vec![];
+            let mut can_lose = true;
+            let mut win = None;
+            let mut longest_loss = 0;
+            for m in moves {
+                let slot = placement.iter().position(|h| *h == m.from).unwrap();
+                let (player, piece) = table.slots[slot];
+                let captured = board.hexes[m.to];
+                let promotes = piece == Piece::Pawn && board::is_queening_hex(m.to, player);
+                if let Some((_, Piece::King)) = captured {
+                    win = Some(1);
+                    break;
+                }
+                if captured.is_none() && !promotes {
+                    let mut child = placement.clone();
+                    child[slot] = m.to;
+                    children.push(table.index(&child, turn.opposite()));
+                    continue;
+                }
+                let mut child = board.clone();
+                logic::make_move(&mut child, m);
+                let probe = tablebase.probe(&child)
+                    .ok_or_else(|| format!("{} needs the table for {}", material.name(), Material::of(&child.hexes).unwrap().name()))?
+                    .before();
+                match probe.wdl {
+                    Wdl::Win => win = Some(win.map_or(probe.plies, |w: u16| w.min(probe.plies))),
+                    Wdl::Draw => can_lose = false,
+                    Wdl::Loss => longest_loss = longest_loss.max(probe.plies)
+                }
+            }
+            if let Some(plies) = win {
+                push(&mut buckets, plies as usize, index);
+            } else if can_lose {
+                children.sort_unstable();
+                children.dedup();
+                assert!(children.len() < CANNOT_LOSE as usize, "too many moves to count");
+                remaining[index] = children.len() as u8;
+                values[index] = longest_loss;
+                if children.is_empty() {
+                    push(&mut buckets, longest_loss as usize, index);
+                }
+            }
+        }
+        let mut plies = 1;
+        while plies < buckets.len() {
+            let bucket = std::mem::take(&mut buckets[plies]);
+            for index in bucket {
+                if values[index] & RESOLVED != 0 {
+                    continue;
+                }
+                values[index] = plies as u16 | RESOLVED;
+                let (placement, turn) = table.position(index).unwrap();
+                for before in table.unmoves(&placement, turn, &reach) {
+                    if values[before] & RESOLVED != 0 {
+                        continue;
+                    }
+                    if plies.is_multiple_of(2) {
+                        // the side to move here loses, so moving here wins
+                        push(&mut buckets, plies + 1, before);
+                    } else if remaining[before] != CANNOT_LOSE {
+                        remaining[before] -= 1;
+                        if remaining[before] == 0 {
+                            // every move loses, the slowest at this distance or through an exit
+                            let longest = (plies as u16 + 1).max(values[before]);
+                            push(&mut buckets, longest as usize, before);
+                        }
+                    }
+                }
+            }
+            plies += 1;
+        }
+        for value in values.iter_mut() {
+            *value = if *value & RESOLVED != 0 { *value & !RESOLVED } else { 0 };
+        }
+        table.values = values;
+        Ok(table)
+    }
+
+    // wins, draws, losses and the longest win, over possible positions
+    pub fn stats(&self) -> (usize, usize, usize, u16) {
+        let (mut wins, mut draws, mut losses, mut longest) = (0, 0, 0, 0);
+        for index in 0..self.len() {
+            if self.position(index).is_none() {
+                continue;
+            }
+            let probe = Probe::from_value(self.values[index]);
+            match probe.wdl {
+                Wdl::Win => {
+                    wins += 1;
+                    longest = longest.max(probe.plies);
+                },
+                Wdl::Draw => draws += 1,
+                Wdl::Loss => losses += 1
+            }
+        }
+        (wins, draws, losses, longest)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let name = self.material.name();
+        let wide = self.values.iter().any(|v| *v > u8::MAX as u16);
+        let mut bytes = vec![];
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(name.len() as u8);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.push(if wide { 2 } else { 1 });
+        bytes.extend_from_slice(&(self.values.len() as u64).to_le_bytes());
+        for value in &self.values {
+            if wide {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            } else {
+                bytes.push(*value as u8);
+            }
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Table, String> {
+        let truncated = || "table file is truncated".to_string();
+        if bytes.len() < 6 || &bytes[..4] != MAGIC {
+            return Err("not a table file".to_string());
+        }
+        if bytes[4] != VERSION {
+            return Err(format!("unsupported table version {}", bytes[4]));
+        }
+        let name_end = 6 + bytes[5] as usize;
+        let name = std::str::from_utf8(bytes.get(6..name_end).ok_or_else(truncated)?).map_err(|e| e.to_string())?;
+        let mut table = Table::empty(&Material::parse(name)?)?;
+        let width = *bytes.get(name_end).ok_or_else(truncated)? as usize;
+        let count = u64::from_le_bytes(bytes.get(name_end + 1..name_end + 9).ok_or_else(truncated)?.try_into().unwrap()) as usize;
+        let body = &bytes[name_end + 9..];
+        if count != table.len() || body.len() != count * width || !(width == 1 || width == 2) {
+            return Err(format!("{}: table has the wrong size", name));
+        }
+        table.values = match width {
+            1 => body.iter().map(|v| *v as u16).collect(),
+            _ => body.chunks(2).map(|v| u16::from_le_bytes([v[0], v[1]])).collect()
+        };
+        Ok(table)
+    }
+}
+
+// for each piece and hex, the hexes it could have come from on an empty board
+struct Reach {
+    origins: HashMap<(Player, Piece), Vec<Vec<usize>>>
+}
+
+impl Reach {
+    fn new(slots: &[(Player, Piece)]) -> Reach {
+        let mut origins = HashMap::new();
+        for &(player, piece) in slots {
+            origins.entry((player, piece)).or_insert_with(|| {
+                let mut from = vec![vec![]; HEX_COUNT];
+                for hex in 0..HEX_COUNT {
+                    let mut hexes: Hexes = [None; HEX_COUNT];
+                    hexes[hex] = Some((player, piece));
+                    for to in logic::piece_moves(&hexes, hex) {
+                        if !from[to].contains(&hex) {
+                            from[to].push(hex);
+                        }
+                    }
+                }
+                from
+            });
+        }
+        Reach { origins }
+    }
+
+    fn origins(&self, player: Player, piece: Piece, to: usize) -> &[usize] {
+        &self.origins[&(player, piece)][to]
+    }
+}
+
+#[derive(Default)]
+pub struct Tablebase {
+    tables: HashMap<Material, Table>
+}
+
+impl Tablebase {
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.tables.keys().map(|m| m.piece_count()).max().unwrap_or(0)
+    }
+
+    pub fn contains(&self, material: &Material) -> bool {
+        self.tables.contains_key(material)
+    }
+
+    pub fn probe(&self, board: &Board) -> Option<Probe> {
+        if board.hexes.iter().flatten().count() > self.max_pieces() {
+            return None;
+        }
+        let material = Material::of(&board.hexes)?;
+        self.tables.get(&material)?.probe(board)
+    }
+
+    // the quickest win, else a draw, else the slowest loss
+    pub fn best_move(&self, board: &Board) -> Option<(Move, Probe)> {
+        self.probe(board)?;
+        let mut best: Option<(Move, Probe)> = None;
+        for m in logic::legal_moves(board) {
+            let probe = if let Some((_, Piece::King)) = board.hexes[m.to] {
+                Probe { wdl: Wdl::Win, plies: 1 }
+            } else {
+                let mut child = board.clone();
+                logic::make_move(&mut child, m);
+                self.probe(&child)?.before()
+            };
+            if best.is_none_or(|(_, b)| probe.rank() > b.rank()) {
+                best = Some((m, probe));
+            }
+        }
+        best
+    }
+
+    // generates material and everything it can turn into, skipping tables
+    // already loaded, and hands each new table to done
+    pub fn generate<F>(&mut self, material: &Material, done: &mut F) -> Result<(), String>
+    where F: FnMut(&Table) -> Result<(), String> {
+        if self.contains(material) {
+            return Ok(());
+        }
+        material.table_size()?;
+        for successor in material.successors() {
+            self.generate(&successor, done)?;
+        }
+        let table = Table::generate(material, self)?;
+        done(&table)?;
+        self.tables.insert(material.clone(), table);
+        Ok(())
+    }
+
+    pub fn save_table(table: &Table, dir: &Path) -> Result<(), String> {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let path = dir.join(format!("{}.{}", table.material.name(), EXTENSION));
+        std::fs::write(&path, table.to_bytes()).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn load_dir(dir: &Path) -> Result<Tablebase, String> {
+        let mut tablebase = Tablebase::default();
+        let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension().is_some_and(|e| e == EXTENSION) {
+                let bytes = std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                let table = Table::from_bytes(&bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+                tablebase.tables.insert(table.material.clone(), table);
+            }
+        }
+        Ok(tablebase)
+    }
+}
+
+pub const DEFAULT_DIR: &str = "tablebases";
+
+pub const USAGE: &str = "usage: hexchess tablebase generate MATERIAL... [--dir DIR]
+       hexchess tablebase probe \"<position>\" [--dir DIR]
+materials are written white v black, like KFvK or KGvKP";
+
+pub fn main(args: &[String]) -> Result<(), String> {
+    let mut dir = DEFAULT_DIR.to_string();
+    let mut words = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--dir" {
+            dir = args.next().cloned().ok_or("--dir needs a value")?;
+        } else {
+            words.push(arg.as_str());
+        }
+    }
+    let dir = Path::new(&dir);
+    match words.split_first() {
+        Some((&"generate", materials)) if !materials.is_empty() => {
+            let mut tablebase = if dir.exists() { Tablebase::load_dir(dir)? } else { Tablebase::default() };
+            for material in materials {
+                let material = Material::parse(material)?;
+                tablebase.generate(&material, &mut |table| {
+                    let (wins, draws, losses, longest) = table.stats();
+                    println!(
+                        "{}: {} wins, {} draws, {} losses, longest win {} plies",
+                        table.material().name(), wins, draws, losses, longest
+                    );
+                    Tablebase::save_table(table, dir)
+                })?;
+            }
+            Ok(())
+        },
+        Some((&"probe", position)) if !position.is_empty() => {
+            let tablebase = Tablebase::load_dir(dir)?;
+            let board = crate::notation::parse_position(&position.join(" "))?;
+            match tablebase.best_move(&board) {
+                Some((m, probe)) => {
+                    println!("{}, best move {}", probe.describe(board.current_turn), crate::notation::move_text(m));
+                    Ok(())
+                },
+                None => match tablebase.probe(&board) {
+                    Some(probe) => {
+                        println!("{}", probe.describe(board.current_turn));
+                        Ok(())
+                    },
+                    None => Err("position is not in the tablebase".to_string())
+                }
+            }
+        },
+        _ => Err(USAGE.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+    use crate::solver;
+
+    // KvKF and KvK, generated once for every test
+    fn tablebase() -> &'static Tablebase {
+        static TABLEBASE: OnceLock<Tablebase> = OnceLock::new();
+        TABLEBASE.get_or_init(|| {
+            let mut tablebase = Tablebase::default();
+            tablebase.generate(&Material::parse("KvKF").unwrap(), &mut |_| Ok(())).unwrap();
+            tablebase
+        })
+    }
+
+    fn board(pieces: &[(usize, Player, Piece)], turn: Player) -> Board {
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        for &(hex, player, piece) in pieces {
+            hexes[hex] = Some((player, piece));
+        }
+        Board::from_hexes(hexes, turn)
+    }
+
+    #[test]
+    fn bare_kings_draw_unless_one_can_be_taken() {
+        let tablebase = tablebase();
+        assert_eq!(tablebase.len(), 2);
+        let apart = board(&[(0, Player::White, Piece::King), (45, Player::Black, Piece::King)], Player::White);
+        assert_eq!(tablebase.probe(&apart), Some(Probe { wdl: Wdl::Draw, plies: 0 }));
+        let touching = board(&[(0, Player::White, Piece::King), (1, Player::Black, Piece::King)], Player::Black);
+        assert_eq!(tablebase.probe(&touching), Some(Probe { wdl: Wdl::Win, plies: 1 }));
+    }
+
+    #[test]
+    fn wins_are_as_long_as_the_solver_says() {
+        let tablebase = tablebase();
+        let position = board(&[
+            (0, Player::White, Piece::King),
+            (3, Player::Black, Piece::King),
+            (21, Player::Black, Piece::Fortress)
+        ], Player::Black);
+        let probe = tablebase.probe(&position).unwrap();
+        assert_eq!(probe, Probe { wdl: Wdl::Win, plies: 5 });
+        assert_eq!(probe.describe(Player::Black), "Black wins in 3");
+        assert!(solver::solve(&position, 2).is_empty());
+        let solutions = solver::solve(&position, 3);
+        assert_eq!(solutions[0].moves, 3);
+        let (m, best) = tablebase.best_move(&position).unwrap();
+        assert_eq!(best, probe);
+        assert!(solutions.iter().any(|s| s.tree.m == m));
+        let mut child = position.clone();
+        logic::make_move(&mut child, m);
+        assert_eq!(tablebase.probe(&child), Some(Probe { wdl: Wdl::Loss, plies: 4 }));
+    }
+
+    #[test]
+    fn a_lone_king_cannot_take_a_fortress() {
+        // taking the fortress would leave a drawn KvK, but one attacker is
+        // not enough to get through its armour
+        let tablebase = tablebase();
+        let position = board(&[
+            (0, Player::White, Piece::King),
+            (3, Player::Black, Piece::King),
+            (1, Player::Black, Piece::Fortress)
+        ], Player::White);
+        assert_eq!(position.threats[1], 1);
+        assert!(!logic::legal_moves(&position).contains(&Move { from: 0, to: 1 }));
+        assert_eq!(tablebase.probe(&position), Some(Probe { wdl: Wdl::Loss, plies: 6 }));
+        let (m, _) = tablebase.best_move(&position).unwrap();
+        assert_ne!(m.to, 1);
+    }
+
+    #[test]
+    fn mirror_images_probe_the_same() {
+        let tablebase = tablebase();
+        for pieces in [
+            [(0, Player::White, Piece::King), (3, Player::Black, Piece::King), (21, Player::Black, Piece::Fortress)],
+            [(40, Player::White, Piece::King), (12, Player::Black, Piece::King), (60, Player::Black, Piece::Fortress)]
+        ] {
+            for turn in [Player::White, Player::Black] {
+                let position = board(&pieces, turn);
+                let mirrored = position.mirrored();
+                assert_ne!(position.hexes, mirrored.hexes);
+                assert_eq!(tablebase.probe(&position), tablebase.probe(&mirrored));
+                let (_, best) = tablebase.best_move(&position).unwrap();
+                assert_eq!(tablebase.best_move(&mirrored).unwrap().1, best);
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_tables_are_refused() {
+        assert!(Material::parse("KGvKP").unwrap().table_size().is_ok());
+        let error = Material::parse("KGvKPP").unwrap().table_size().unwrap_err();
+        assert!(error.starts_with("KGvKPP: too many pieces"));
+        assert!(Tablebase::default().generate(&Material::parse("KQNvKP").unwrap(), &mut |_| Ok(())).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use ggez::graphics::Color;
+use crate::board::{Board, Player};
+use crate::geometry::COS_PI_6;
+
+// Colours for everything the GUI draws. The board uses three tile colours
+// so no two neighbouring hexes look the same, a theme with one board
+// colour just repeats it.
+#[derive(Clone, Copy, Debug)]
+pub struct Theme {
+    pub name: &'static str,
+    pub background: Color,
+    pub tiles: [Color; 3],
+    // the hex under the pointer
+    pub pointer: Color,
+    pub selected: Color,
+    // the selected piece's moves, and the one under the pointer
+    pub moves: Color,
+    pub target: Color,
+    pub hint: Color,
+    pub warning: Color,
+    pub last_move: Color,
+    // the attack heat map at full strength, see overlay
+    pub heat: (Color, Color),
+    pub armoured: Color,
+    pub breached: Color,
+    pub text: Color,
+    // fill and outline of each side's pieces
+    pub white_piece: (Color, Color),
+    pub black_piece: (Color, Color)
+}
+
+const fn rgb(r: f32, g: f32, b: f32) -> Color {
+    Color::new(r, g, b, 1.0)
+}
+
+pub const THEMES: [Theme; 4] = [
+    Theme {
+        name: "walnut",
+        background: Color::BLACK,
+        tiles: [rgb(0.5, 0.25, 0.0); 3],
+        pointer: rgb(1.0, 0.5, 0.0),
+        selected: rgb(1.0, 0.0, 0.0),
+        moves: rgb(0.0, 0.5, 0.0),
+        target: rgb(0.0, 1.0, 0.0),
+        hint: rgb(0.2, 0.4, 0.8),
+        warning: rgb(0.8, 0.0, 0.8),
+        last_move: rgb(0.7, 0.55, 0.2),
+        heat: (rgb(1.0, 0.85, 0.3), rgb(0.3, 0.6, 1.0)),
+        armoured: rgb(0.6, 0.75, 0.9),
+        breached: rgb(1.0, 0.2, 0.1),
+        text: Color::WHITE,
+        white_piece: (Color::WHITE, rgb(0.1, 0.1, 0.1)),
+        black_piece: (rgb(0.1, 0.1, 0.1), rgb(0.85, 0.85, 0.85))
+    },
+    Theme {
+        name: "glinski",
+        background: rgb(0.15, 0.12, 0.1),
+        tiles: [rgb(0.82, 0.66, 0.45), rgb(0.64, 0.46, 0.27), rgb(0.45, 0.29, 0.13)],
+        pointer: rgb(0.95, 0.75, 0.3),
+        selected: rgb(0.85, 0.2, 0.15),
+        moves: rgb(0.45, 0.6, 0.3),
+        target: rgb(0.55, 0.85, 0.35),
+        hint: rgb(0.35, 0.55, 0.85),
+        warning: rgb(0.75, 0.25, 0.7),
+        last_move: rgb(0.85, 0.78, 0.35),
+        heat: (rgb(1.0, 0.55, 0.2), rgb(0.25, 0.45, 0.95)),
+        armoured: rgb(0.35, 0.55, 0.8),
+        breached: rgb(0.9, 0.15, 0.1),
+        text: rgb(0.95, 0.9, 0.8),
+        white_piece: (rgb(0.97, 0.95, 0.9), rgb(0.2, 0.15, 0.1)),
+        black_piece: (rgb(0.15, 0.12, 0.1), rgb(0.9, 0.85, 0.75))
+    },
+    Theme {
+        name: "ocean",
+        background: rgb(0.05, 0.08, 0.15),
+        tiles: [rgb(0.55, 0.7, 0.8), rgb(0.35, 0.52, 0.66), rgb(0.2, 0.35, 0.5)],
+        pointer: rgb(0.95, 0.85, 0.4),
+        selected: rgb(0.9, 0.35, 0.3),
+        moves: rgb(0.3, 0.65, 0.55),
+        target: rgb(0.45, 0.9, 0.7),
+        hint: rgb(0.75, 0.6, 0.95),
+        warning: rgb(0.95, 0.4, 0.75),
+        last_move: rgb(0.75, 0.8, 0.45),
+        heat: (rgb(1.0, 0.6, 0.3), rgb(0.4, 0.2, 0.8)),
+        armoured: rgb(0.9, 0.95, 1.0),
+        breached: rgb(1.0, 0.3, 0.25),
+        text: rgb(0.85, 0.92, 1.0),
+        white_piece: (rgb(0.98, 0.98, 1.0), rgb(0.05, 0.1, 0.2)),
+        black_piece: (rgb(0.08, 0.1, 0.18), rgb(0.85, 0.9, 1.0))
+    },
+    Theme {
+        name: "contrast",
+        background: Color::BLACK,
+        tiles: [rgb(0.75, 0.75, 0.75), rgb(0.5, 0.5, 0.5), rgb(0.3, 0.3, 0.3)],
+        pointer: rgb(1.0, 1.0, 0.0),
+        selected: rgb(1.0, 0.0, 0.0),
+        moves: rgb(0.0, 0.6, 1.0),
+        target: rgb(0.0, 1.0, 1.0),
+        hint: rgb(0.0, 1.0, 0.0),
+        warning: rgb(1.0, 0.0, 1.0),
+        last_move: rgb(1.0, 0.6, 0.0),
+        heat: (rgb(1.0, 1.0, 0.0), rgb(0.0, 0.8, 1.0)),
+        armoured: rgb(0.0, 1.0, 0.0),
+        breached: rgb(1.0, 0.0, 0.0),
+        text: Color::WHITE,
+        white_piece: (Color::WHITE, Color::BLACK),
+        black_piece: (Color::BLACK, Color::WHITE)
+    }
+];
+
+impl Theme {
+    pub fn parse(name: &str) -> Result<Theme, String> {
+        THEMES.iter()
+            .find(|theme| theme.name.eq_ignore_ascii_case(name))
+            .cloned()
+            .ok_or_else(|| format!("unknown theme: {}", name))
+    }
+
+    pub fn next(&self) -> Theme {
+        let index = THEMES.iter().position(|theme| theme.name == self.name).unwrap_or(0);
+        THEMES[(index + 1) % THEMES.len()]
+    }
+
+    // hexes along a rank sit two half widths apart and the ranks are
+    // offset by one, so counting half widths mod 3 colours neighbours apart
+    pub fn tile(&self, board: &Board, hex: usize) -> Color {
+        let half_widths = (board.positions[hex][0] / COS_PI_6).round() as i32;
+        self.tiles[half_widths.rem_euclid(3) as usize]
+    }
+
+    pub fn piece(&self, player: Player) -> (Color, Color) {
+        match player {
+            Player::White => self.white_piece,
+            Player::Black => self.black_piece
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PieceSet {
+    // drawn shapes, see glyphs
+    Shapes,
+    // the pieces' letters in the GUI font
+    Letters
+}
+
+impl PieceSet {
+    pub fn name(self) -> &'static str {
+        match self {
+            PieceSet::Shapes => "shapes",
+            PieceSet::Letters => "letters"
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<PieceSet, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "shapes" => Ok(PieceSet::Shapes),
+            "letters" => Ok(PieceSet::Letters),
+            _ => Err(format!("unknown piece set: {}", name))
+        }
+    }
+
+    pub fn next(self) -> PieceSet {
+        match self {
+            PieceSet::Shapes => PieceSet::Letters,
+            PieceSet::Letters => PieceSet::Shapes
+        }
+    }
+}
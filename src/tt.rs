@@ -0,0 +1,287 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::logic::Move;
+use crate::search::{MATE, MAX_PLY};
+
+pub const DEFAULT_HASH_MB: usize = 16;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Entry {
+    pub key: u64,
+    pub depth: u8,
+    pub bound: Bound,
+    pub score: i32,
+    pub best_move: Option<Move>,
+    generation: u8
+}
+
+// An entry packed into one word:
+//   bits  0..16  score
+//   bits 16..24  depth
+//   bits 24..26  bound
+//   bits 26..34  generation
+//   bits 34..48  move, from and to
+//   bit  48      has a move
+//   bit  49      slot in use
+const USED: u64 = 1 << 49;
+const HAS_MOVE: u64 = 1 << 48;
+
+impl Entry {
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2
+        };
+        let m = match self.best_move {
+            Some(m) => HAS_MOVE | (m.from as u64) << 34 | (m.to as u64) << 41,
+            None => 0
+        };
+        USED
+            | self.score as i16 as u16 as u64
+            | (self.depth as u64) << 16
+            | bound << 24
+            | (self.generation as u64) << 26
+            | m
+    }
+
+    fn unpack(key: u64, data: u64) -> Entry {
+        let bound = match (data >> 24) & 3 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper
+        };
+        let best_move = if data & HAS_MOVE != 0 {
+            Some(Move {
+                from: ((data >> 34) & 127) as usize,
+                to: ((data >> 41) & 127) as usize
+            })
+        } else {
+            None
+        };
+        Entry {
+            key,
+            depth: (data >> 16) as u8,
+            bound,
+            score: data as u16 as i16 as i32,
+            best_move,
+            generation: (data >> 26) as u8
+        }
+    }
+}
+
+// Shared by all search threads without locks. A slot holds the key xored
+// with the data next to the data, so an entry torn by two threads writing
+// at once no longer matches its key and is ignored.
+//
+// A fixed number of slots, a slot keeps the deeper of two searches
+// unless its entry is left over from an earlier search.
+pub struct TranspositionTable {
+    slots: Vec<[AtomicU64; 2]>,
+    generation: u8,
+    hits: AtomicU64
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let bytes = size_mb.max(1) * 1024 * 1024;
+        let slots = bytes / std::mem::size_of::<[AtomicU64; 2]>();
+        // a power of two so the key can be masked into an index
+        let slots = 1 << (usize::BITS - 1 - slots.leading_zeros());
+        TranspositionTable {
+            slots: (0..slots).map(|_| [AtomicU64::new(0), AtomicU64::new(0)]).collect(),
+            generation: 0,
+            hits: AtomicU64::new(0)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn clear(&mut self) {
+        for slot in self.slots.iter_mut() {
+            *slot[0].get_mut() = 0;
+            *slot[1].get_mut() = 0;
+        }
+        self.generation = 0;
+        *self.hits.get_mut() = 0;
+    }
+
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    fn index(&self, key: u64) -> usize {
+        key as usize & (self.slots.len() - 1)
+    }
+
+    fn load(&self, index: usize) -> Option<Entry> {
+        let checked = self.slots[index][0].load(Ordering::Relaxed);
+        let data = self.slots[index][1].load(Ordering::Relaxed);
+        if data & USED == 0 {
+            return None;
+        }
+        Some(Entry::unpack(checked ^ data, data))
+    }
+
+    // score comes back relative to ply
+    pub fn probe(&self, key: u64, ply: u32) -> Option<Entry> {
+        match self.load(self.index(key)) {
+            Some(entry) if entry.key == key => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(Entry {
+                    score: score_from_tt(entry.score, ply),
+                    ..entry
+                })
+            },
+            _ => None
+        }
+    }
+
+    pub fn store(&self, key: u64, depth: u32, bound: Bound, score: i32, best_move: Option<Move>, ply: u32) {
+        let index = self.index(key);
+        let depth = depth.min(u8::MAX as u32) as u8;
+        let replace = match self.load(index) {
+            None => true,
+            Some(old) => {
+                old.key == key
+                || old.generation != self.generation
+                || depth >= old.depth
+            }
+        };
+        if replace {
+            let data = Entry {
+                key,
+                depth,
+                bound,
+                score: score_to_tt(score, ply),
+                best_move,
+                generation: self.generation
+            }.pack();
+            self.slots[index][0].store(key ^ data, Ordering::Relaxed);
+            self.slots[index][1].store(data, Ordering::Relaxed);
+        }
+    }
+}
+
+// mate scores count plies from the root, in the table they count from
+// the stored position so they stay right when reached by another path
+pub fn score_to_tt(score: i32, ply: u32) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score + ply as i32
+    } else if score <= -(MATE - MAX_PLY as i32) {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: u32) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score - ply as i32
+    } else if score <= -(MATE - MAX_PLY as i32) {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use super::*;
+    use crate::board::{Board, Hexes, Player, HEX_COUNT};
+    use crate::eval::EvalWeights;
+    use crate::pieces::Piece;
+    use crate::search::{Engine, EngineOptions, Limits};
+
+    fn depth_limit(depth: u32) -> Limits {
+        Limits {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn searching_twice_reuses_entries() {
+        let board = Board::new();
+        let stop = AtomicBool::new(false);
+        let mut engine = Engine::with_options(EvalWeights::default(), EngineOptions { hash_mb: 1, ..Default::default() });
+        let first = engine.search(&board, depth_limit(3), &stop, |_| {}).unwrap();
+        let hits = engine.tt().hits();
+        let second = engine.search(&board, depth_limit(3), &stop, |_| {}).unwrap();
+        assert!(engine.tt().hits() > hits);
+        assert!(second.nodes < first.nodes);
+        assert_eq!(first.best_move(), second.best_move());
+    }
+
+    #[test]
+    fn mate_scores_are_relative_to_the_stored_position() {
+        let tt = TranspositionTable::new(1);
+        tt.store(42, 4, Bound::Exact, MATE - 5, None, 3);
+        assert_eq!(tt.probe(42, 3).unwrap().score, MATE - 5);
+        assert_eq!(tt.probe(42, 1).unwrap().score, MATE - 3);
+        tt.store(43, 4, Bound::Exact, -(MATE - 6), None, 2);
+        assert_eq!(tt.probe(43, 4).unwrap().score, -(MATE - 8));
+        tt.store(44, 4, Bound::Exact, 120, None, 2);
+        assert_eq!(tt.probe(44, 7).unwrap().score, 120);
+    }
+
+    #[test]
+    fn mate_score_survives_a_second_search() {
+        let mut hexes: Hexes = [None; HEX_COUNT];
+        hexes[3] = Some((Player::Black, Piece::King));
+        hexes[20] = Some((Player::White, Piece::Queen));
+        hexes[87] = Some((Player::White, Piece::King));
+        hexes[90] = Some((Player::Black, Piece::Pawn));
+        let board = Board::from_hexes(hexes, Player::White);
+        let stop = AtomicBool::new(false);
+        let mut engine = Engine::with_options(EvalWeights::default(), EngineOptions { hash_mb: 1, ..Default::default() });
+        let first = engine.search(&board, depth_limit(4), &stop, |_| {}).unwrap();
+        let second = engine.search(&board, depth_limit(4), &stop, |_| {}).unwrap();
+        assert_eq!(first.score, second.score);
+        assert!(crate::search::is_mate_score(second.score));
+        assert!(second.score > 0);
+    }
+
+    #[test]
+    fn deeper_entries_are_kept() {
+        let tt = TranspositionTable::new(1);
+        let clash = 7 + tt.len() as u64;
+        tt.store(7, 5, Bound::Lower, 10, None, 0);
+        tt.store(clash, 2, Bound::Lower, 20, None, 0);
+        assert!(tt.probe(clash, 0).is_none());
+        assert_eq!(tt.probe(7, 0).unwrap().score, 10);
+        tt.store(clash, 6, Bound::Lower, 30, None, 0);
+        assert!(tt.probe(7, 0).is_none());
+        assert_eq!(tt.probe(clash, 0).unwrap().score, 30);
+    }
+
+    #[test]
+    fn entries_pack_into_a_word() {
+        let tt = TranspositionTable::new(1);
+        let m = Move { from: 90, to: 0 };
+        tt.store(u64::MAX, 200, Bound::Upper, -(MATE - 3), Some(m), 0);
+        let entry = tt.probe(u64::MAX, 0).unwrap();
+        assert_eq!(entry.depth, 200);
+        assert_eq!(entry.bound, Bound::Upper);
+        assert_eq!(entry.score, -(MATE - 3));
+        assert_eq!(entry.best_move, Some(m));
+        assert!(tt.probe(0, 0).is_none());
+    }
+}
@@ -0,0 +1,117 @@
+use std::f32::consts::PI;
+use crate::board::{self, Board, Player, HEX_COUNT};
+use crate::geometry::COS_PI_6;
+
+// Where each hex is drawn. Board positions have White's side at the
+// bottom with pointy-top hexes; the view turns them about the middle of
+// the board, half a turn to put Black at the bottom and a twelfth of a
+// turn for flat-top hexes. Drawing and picking both go through layout so
+// they always agree.
+
+// the middle of the board in board positions
+pub const CENTRE: [f32; 2] = [5.0 * COS_PI_6, 7.5];
+// degrees a direction can be off the way to a hex and still reach it
+pub const MAX_STEP_TURN: f32 = 29.0;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct View {
+    pub flipped: bool,
+    // the side at the bottom follows the player to move
+    pub auto_flip: bool,
+    pub rotated: bool
+}
+
+impl View {
+    // bottom is the side that would sit at the bottom unflipped
+    pub fn upside_down(&self, bottom: Player) -> bool {
+        let auto = self.auto_flip && bottom == Player::Black;
+        self.flipped != auto
+    }
+
+    pub fn rotation(&self, bottom: Player) -> f32 {
+        let half_turn = if self.upside_down(bottom) { PI } else { 0.0 };
+        let twelfth = if self.rotated { PI / 6.0 } else { 0.0 };
+        half_turn + twelfth
+    }
+
+    // turning the board a twelfth swaps its width across the corners with
+    // its width across the sides, shrinking it keeps it in the same space
+    pub fn scale(&self) -> f32 {
+        if self.rotated { COS_PI_6 } else { 1.0 }
+    }
+
+    // a point given in board positions, relative to the middle of the
+    // board in tiles of the scaled size
+    pub fn place(&self, pos: [f32; 2], bottom: Player) -> [f32; 2] {
+        let (sin, cos) = self.rotation(bottom).sin_cos();
+        let x = pos[0] - CENTRE[0];
+        let y = pos[1] - CENTRE[1];
+        [x * cos - y * sin, x * sin + y * cos]
+    }
+
+    pub fn layout(&self, board: &Board, bottom: Player) -> [[f32; 2]; HEX_COUNT] {
+        let mut layout = [[0.0; 2]; HEX_COUNT];
+        for (out, pos) in layout.iter_mut().zip(board.positions.iter()) {
+            *out = self.place(*pos, bottom);
+        }
+        layout
+    }
+
+    // the neighbour of hex drawn nearest a direction on screen, given in
+    // degrees clockwise from right, a direction halfway between two goes
+    // to neither
+    pub fn step(&self, board: &Board, bottom: Player, hex: usize, direction: f32) -> Option<usize> {
+        let from = self.place(board.positions[hex], bottom);
+        board::adjacent(hex).into_iter()
+            .map(|next| {
+                let to = self.place(board.positions[next], bottom);
+                let heading = (to[1] - from[1]).atan2(to[0] - from[0]).to_degrees();
+                let off = (heading - direction).rem_euclid(360.0);
+                (next, off.min(360.0 - off))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .filter(|(_, off)| *off < MAX_STEP_TURN)
+            .map(|(next, _)| next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CENTRE_HEX: usize = 45;
+
+    #[test]
+    fn steps_follow_the_screen() {
+        let board = Board::new();
+        let view = View::default();
+        assert_eq!(view.step(&board, Player::White, CENTRE_HEX, 0.0), board::right(CENTRE_HEX));
+        assert_eq!(view.step(&board, Player::White, CENTRE_HEX, 225.0), board::up_left(CENTRE_HEX));
+        assert_eq!(view.step(&board, Player::White, CENTRE_HEX, 45.0), board::down_right(CENTRE_HEX));
+        // straight up is between two hexes
+        assert_eq!(view.step(&board, Player::White, CENTRE_HEX, 270.0), None);
+        let flipped = View { flipped: true, ..View::default() };
+        assert_eq!(flipped.step(&board, Player::White, CENTRE_HEX, 0.0), board::left(CENTRE_HEX));
+        assert_eq!(flipped.step(&board, Player::White, CENTRE_HEX, 225.0), board::down_right(CENTRE_HEX));
+    }
+
+    #[test]
+    fn turned_boards_step_where_they_are_drawn() {
+        let board = Board::new();
+        let view = View { rotated: true, ..View::default() };
+        // up-left is drawn straight up once the board turns a twelfth
+        assert_eq!(view.step(&board, Player::White, CENTRE_HEX, 270.0), board::up_left(CENTRE_HEX));
+        assert_eq!(view.step(&board, Player::White, CENTRE_HEX, 315.0), board::up_right(CENTRE_HEX));
+        assert_eq!(view.step(&board, Player::White, CENTRE_HEX, 45.0), board::right(CENTRE_HEX));
+        assert_eq!(view.step(&board, Player::White, CENTRE_HEX, 90.0), board::down_right(CENTRE_HEX));
+        assert_eq!(view.step(&board, Player::White, CENTRE_HEX, 0.0), None);
+        for direction in [0.0, 45.0, 90.0, 135.0, 180.0, 225.0, 270.0, 315.0] {
+            if let Some(next) = view.step(&board, Player::White, CENTRE_HEX, direction) {
+                let from = view.place(board.positions[CENTRE_HEX], Player::White);
+                let to = view.place(board.positions[next], Player::White);
+                let heading = (to[1] - from[1]).atan2(to[0] - from[0]).to_degrees().rem_euclid(360.0);
+                assert!((heading - direction).abs() < MAX_STEP_TURN);
+            }
+        }
+    }
+}
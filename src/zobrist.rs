@@ -0,0 +1,48 @@
+use crate::board::*;
+
+const PIECE_KINDS: usize = 7;
+
+const fn split_mix(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    (state, z ^ (z >> 31))
+}
+
+const fn generate_keys() -> [[u64; 2 * PIECE_KINDS]; HEX_COUNT] {
+    let mut keys = [[0; 2 * PIECE_KINDS]; HEX_COUNT];
+    let mut state = 0x4845_5843_4845_5353;
+    let mut hex = 0;
+    while hex < HEX_COUNT {
+        let mut kind = 0;
+        while kind < 2 * PIECE_KINDS {
+            let (next, key) = split_mix(state);
+            state = next;
+            keys[hex][kind] = key;
+            kind += 1;
+        }
+        hex += 1;
+    }
+    keys
+}
+
+const PIECE_KEYS: [[u64; 2 * PIECE_KINDS]; HEX_COUNT] = generate_keys();
+const BLACK_TO_MOVE: u64 = split_mix(0x5455_524E).1;
+
+pub fn hash(hexes: &Hexes, current_turn: Player) -> u64 {
+    let mut key = match current_turn {
+        Player::White => 0,
+        Player::Black => BLACK_TO_MOVE
+    };
+    for (hex, contents) in hexes.iter().enumerate() {
+        if let Some((player, piece)) = contents {
+            let kind = *piece as usize + match player {
+                Player::White => 0,
+                Player::Black => PIECE_KINDS
+            };
+            key ^= PIECE_KEYS[hex][kind];
+        }
+    }
+    key
+}